@@ -1,28 +1,356 @@
-#[derive(Debug, PartialEq, Clone)]
-pub struct Battery {
-    val: Option<String>,
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::block::check_placeholder;
+use crate::click;
+use crate::notify::{Notifier, Notify, Urgency};
+use crate::sys::Fs;
+use crate::{Args, Block, Config};
+
+// {bat}         charge of all batteries together, in percent
+// {bat.status}  Charging, Discharging, Full or Not charging
+// {bat.ac}      `on` or `off`, whether the AC adapter is plugged in
+// {bat.watts}   power drawn from or charging the batteries
+// {bat.time}    hours:minutes until empty when discharging, until full when charging
+//
+// [bat]
+// notify_below = [20, 10]                  # desktop notification, once each
+// critical_below = 5
+// critical_command = "systemctl suspend"   # run once
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+const PLACEHOLDERS: &[&str] = &["bat", "bat.status", "bat.ac", "bat.watts", "bat.time"];
+
+/// The batteries summed up by energy, so a full 24Wh and an empty 72Wh
+/// battery make 25% and not 50%.
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Reading {
+    /// Wh
+    energy_now: f64,
+    /// Wh
+    energy_full: f64,
+    /// W
+    power: f64,
+    status: String,
+    /// `None` without an AC adapter in sysfs.
+    ac: Option<bool>,
 }
 
-impl Battery {
-    pub fn init() -> Battery {
-        Battery { val: None }
+impl Reading {
+    fn percent(&self) -> f64 {
+        self.energy_now / self.energy_full * 100.0
     }
+}
+
+/// What to do when the charge runs low, each firing once per discharge cycle.
+struct Alerts {
+    /// Percentages to notify below, highest first.
+    notify_below: Vec<f64>,
+    /// Lowest of `notify_below` notified in the current discharge cycle.
+    notified: Option<usize>,
+    critical_below: Option<f64>,
+    critical_command: Option<String>,
+    critical_fired: bool,
+    notifier: Box<dyn Notify>,
+}
+
+impl Alerts {
+    fn new(config: &Config) -> crate::Result<Alerts> {
+        let number = |v: &toml::Value| match v {
+            toml::Value::Integer(i) => Some(*i as f64),
+            toml::Value::Float(f) => Some(*f),
+            _ => None,
+        };
+
+        let mut notify_below = match config.block("bat").and_then(|t| t.get("notify_below")) {
+            Some(toml::Value::Array(a)) => a.iter().map(number).collect::<Option<Vec<_>>>(),
+            Some(_) => None,
+            None => Some(Vec::new()),
+        }
+        .ok_or_else(|| config.error_at(&["bat", "notify_below"], "`bat.notify_below` must be an array of percentages"))?;
+        notify_below.sort_by(|a, b| b.total_cmp(a));
+
+        let critical_below = match config.block("bat").and_then(|t| t.get("critical_below")) {
+            Some(v) => Some(number(v).ok_or_else(|| {
+                config.error_at(&["bat", "critical_below"], "`bat.critical_below` must be a percentage")
+            })?),
+            None => None,
+        };
+
+        Ok(Alerts {
+            notify_below,
+            notified: None,
+            critical_below,
+            critical_command: config.block_str("bat", "critical_command")?.map(|c| c.to_string()),
+            critical_fired: false,
+            notifier: Box::new(Notifier::new()),
+        })
+    }
+
+    fn check(&mut self, r: Option<&Reading>) {
+        let r = match r {
+            Some(r) if r.status == "Discharging" => r,
+            // plugging in starts a new cycle
+            _ => {
+                self.notified = None;
+                self.critical_fired = false;
+                return;
+            }
+        };
+        let percent = r.percent();
+
+        // only the lowest threshold passed, when several are at once
+        let passed = self.notify_below.iter().rposition(|&t| percent < t);
+        if let Some(i) = passed.filter(|&i| self.notified.is_none_or(|n| i > n)) {
+            self.notified = Some(i);
+            let body = format!("{:.0}% left", percent);
+            if let Err(e) = self.notifier.send("Battery low", &body, Urgency::Normal) {
+                eprintln!("Error: could not send a notification: {}", e);
+            }
+        }
 
-    pub fn update(&mut self) {
-        match std::fs::read_to_string("/sys/class/power_supply/BAT0/capacity") {
-            Ok(s) => self.val = Some(s),
-            Err(e) => {
-                self.val = None;
-                println!("Error: {}", e);
-            },
+        if !self.critical_fired && self.critical_below.is_some_and(|c| percent < c) {
+            self.critical_fired = true;
+            let body = format!("{:.0}% left", percent);
+            if let Err(e) = self.notifier.send("Battery critical", &body, Urgency::Critical) {
+                eprintln!("Error: could not send a notification: {}", e);
+            }
+            if let Some(cmd) = &self.critical_command {
+                click::exec(cmd, &[]);
+            }
         }
     }
+}
+
+pub struct Battery {
+    val: Option<Reading>,
+    alerts: Alerts,
+    fs: Arc<dyn Fs>,
+}
+
+impl Block for Battery {
+    fn init(config: &Config) -> crate::Result<Battery> {
+        Ok(Battery {
+            val: None,
+            alerts: Alerts::new(config)?,
+            fs: config.fs(),
+        })
+    }
+
+    fn update(&mut self) {
+        // a desktop has no batteries, which hides the placeholders
+        self.val = read(&*self.fs, Path::new(POWER_SUPPLY));
+        self.alerts.check(self.val.as_ref());
+    }
 
-    pub fn output(&self) -> Option<String> {
-        match &self.val {
-            Some(val) => Some(val.to_string()),
+    fn output(&self, placeholder: &str, _args: &Args) -> Option<String> {
+        let r = self.val.as_ref()?;
+        match placeholder {
+            "bat" => Some(format!("{:.0}", r.percent())),
+            "bat.status" => Some(r.status.clone()),
+            "bat.ac" => r.ac.map(|ac| if ac { "on" } else { "off" }.to_string()),
+            "bat.watts" => Some(format!("{:.1}", r.power)),
+            "bat.time" => {
+                let hours = match r.status.as_str() {
+                    "Discharging" => r.energy_now / r.power,
+                    "Charging" => (r.energy_full - r.energy_now).max(0.0) / r.power,
+                    _ => return None,
+                };
+                if !hours.is_finite() {
+                    return None;
+                }
+                let minutes = (hours * 60.0).round() as u64;
+                Some(format!("{}:{:02}", minutes / 60, minutes % 60))
+            }
             _ => None,
         }
+    }
+
+    fn check_args(&self, placeholder: &str, _args: &Args) -> crate::Result<()> {
+        check_placeholder(placeholder, PLACEHOLDERS)
+    }
+
+    fn placeholders() -> &'static [&'static str] {
+        PLACEHOLDERS
+    }
+
+    fn config_keys() -> &'static [&'static str] {
+        &["notify_below", "critical_below", "critical_command"]
+    }
+}
+
+/// Reads every battery and the AC adapter in `dir`, `None` without batteries.
+fn read(fs: &dyn Fs, dir: &Path) -> Option<Reading> {
+    let entries = fs.list(dir).ok()?;
+
+    let mut r = Reading::default();
+    let mut statuses = Vec::new();
+
+    for name in entries {
+        let supply = dir.join(name);
+        match read_str(fs, &supply, "type").as_deref() {
+            Some("Mains") => {
+                let online = read_num(fs, &supply, "online").is_some_and(|o| o > 0.0);
+                r.ac = Some(r.ac.unwrap_or(false) || online);
+            }
+            // skip the batteries of mice and headsets
+            Some("Battery") if read_str(fs, &supply, "scope").as_deref() != Some("Device") => {
+                let (now, full, power) = match read_energy(fs, &supply) {
+                    Some(e) => e,
+                    None => continue,
+                };
+                r.energy_now += now;
+                r.energy_full += full;
+                r.power += power;
+                statuses.push(read_str(fs, &supply, "status").unwrap_or_default());
+            }
+            _ => {}
+        }
+    }
+
+    if statuses.is_empty() || r.energy_full <= 0.0 {
+        return None;
+    }
+
+    let any = |s: &str| statuses.iter().any(|st| st == s);
+    r.status = if any("Charging") {
+        "Charging"
+    } else if any("Discharging") {
+        "Discharging"
+    } else if statuses.iter().all(|s| s == "Full") {
+        "Full"
+    } else {
+        "Not charging"
+    }
+    .to_string();
+    Some(r)
+}
+
+/// Current and full energy in Wh and power in W of the battery in `dir`.
+///
+/// Batteries report either energy in µWh and power in µW, or charge in µAh
+/// and current in µA, which are turned into energy with the voltage.
+fn read_energy(fs: &dyn Fs, dir: &Path) -> Option<(f64, f64, f64)> {
+    // some drivers report the flow out of the battery as negative
+    let flow = |name| read_num(fs, dir, name).unwrap_or(0.0).abs() / 1e6;
+
+    if let (Some(now), Some(full)) = (read_num(fs, dir, "energy_now"), read_num(fs, dir, "energy_full")) {
+        return Some((now / 1e6, full / 1e6, flow("power_now")));
+    }
+
+    let now = read_num(fs, dir, "charge_now")?;
+    let full = read_num(fs, dir, "charge_full")?;
+    let volts = read_num(fs, dir, "voltage_min_design").or_else(|| read_num(fs, dir, "voltage_now"))? / 1e6;
+    let amps = flow("current_now");
+    Some((now / 1e6 * volts, full / 1e6 * volts, amps * volts))
+}
+
+fn read_str(fs: &dyn Fs, dir: &Path, name: &str) -> Option<String> {
+    fs.read(&dir.join(name)).ok().map(|s| s.trim().to_string())
+}
+
+fn read_num(fs: &dyn Fs, dir: &Path, name: &str) -> Option<f64> {
+    read_str(fs, dir, name)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::{fixture, sampled, MockFs};
+    use std::sync::Mutex;
+
+    /// Keeps the notifications instead of showing them.
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Notify for Recorder {
+        fn send(&mut self, summary: &str, body: &str, _urgency: Urgency) -> crate::Result<()> {
+            self.0.lock().unwrap().push(format!("{}: {}", summary, body));
+            Ok(())
+        }
+    }
+
+    fn output(bat: &Battery, placeholder: &str) -> Option<String> {
+        bat.output(placeholder, &Args::default())
+    }
+
+    #[test]
+    fn sums_up_energy_and_charge_batteries() {
+        // BAT0 reports energy, BAT1 charge at 11.4V, the mouse is left out
+        let bat: Battery = sampled("", fixture("laptop"));
+        assert_eq!(output(&bat, "bat").as_deref(), Some("53"));
+        assert_eq!(output(&bat, "bat.status").as_deref(), Some("Discharging"));
+        assert_eq!(output(&bat, "bat.ac").as_deref(), Some("off"));
+        assert_eq!(output(&bat, "bat.watts").as_deref(), Some("8.0"));
+        assert_eq!(output(&bat, "bat.time").as_deref(), Some("4:44"));
+    }
+
+    #[test]
+    fn no_batteries_hides_everything() {
+        let bat: Battery = sampled("", fixture("desktop"));
+        for p in PLACEHOLDERS {
+            assert_eq!(output(&bat, p), None);
+        }
+    }
+
+    #[test]
+    fn charging_wins_and_counts_to_full() {
+        let fs = MockFs::default();
+        fs.set("/sys/class/power_supply/AC/type", "Mains\n");
+        fs.set("/sys/class/power_supply/AC/online", "1\n");
+        for (name, status, now) in [("BAT0", "Charging", "20000000"), ("BAT1", "Full", "40000000")] {
+            let dir = format!("/sys/class/power_supply/{}", name);
+            fs.set(&format!("{}/type", dir), "Battery\n");
+            fs.set(&format!("{}/status", dir), status);
+            fs.set(&format!("{}/energy_now", dir), now);
+            fs.set(&format!("{}/energy_full", dir), "40000000");
+            fs.set(&format!("{}/power_now", dir), "-10000000");
+        }
+        let bat: Battery = sampled("", Arc::new(fs));
+        assert_eq!(output(&bat, "bat").as_deref(), Some("75"));
+        assert_eq!(output(&bat, "bat.status").as_deref(), Some("Charging"));
+        assert_eq!(output(&bat, "bat.ac").as_deref(), Some("on"));
+        assert_eq!(output(&bat, "bat.time").as_deref(), Some("1:00"));
+    }
+
+    #[test]
+    fn alerts_fire_once_per_discharge() {
+        let config = Config::parse("[bat]\nnotify_below = [10, 20]\ncritical_below = 5\n").unwrap();
+        let mut alerts = Alerts::new(&config).unwrap();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        alerts.notifier = Box::new(Recorder(sent.clone()));
+        assert_eq!(alerts.notify_below, [20.0, 10.0]);
+
+        let reading = |percent: f64, status: &str| Reading {
+            energy_now: percent,
+            energy_full: 100.0,
+            status: status.to_string(),
+            ..Reading::default()
+        };
+
+        alerts.check(Some(&reading(30.0, "Discharging")));
+        assert_eq!(alerts.notified, None);
+        // jumping past both only notifies about the lowest
+        alerts.check(Some(&reading(9.0, "Discharging")));
+        assert_eq!(alerts.notified, Some(1));
+        alerts.check(Some(&reading(15.0, "Discharging")));
+        assert_eq!(alerts.notified, Some(1));
+        assert!(!alerts.critical_fired);
+
+        alerts.check(Some(&reading(4.0, "Discharging")));
+        assert!(alerts.critical_fired);
+
+        assert_eq!(*sent.lock().unwrap(), ["Battery low: 9% left", "Battery critical: 4% left"]);
+
+        alerts.check(Some(&reading(4.0, "Charging")));
+        assert_eq!(alerts.notified, None);
+        assert!(!alerts.critical_fired);
+    }
 
+    #[test]
+    fn alert_thresholds_must_be_numbers() {
+        let config = Config::parse("[bat]\nnotify_below = [\"low\"]\n").unwrap();
+        let e = Alerts::new(&config).err().unwrap();
+        assert_eq!(e.to_string(), "`config.toml` line 2: `bat.notify_below` must be an array of percentages");
     }
 }
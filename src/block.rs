@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::format::{Args, ErrorKind, Format, FormatError, Placeholder, Token};
+use crate::output::Segment;
+use crate::schema;
+use crate::statuscmd::MAX_SIGNAL;
+use crate::click::Actions;
+use crate::style::Style;
+use crate::worker::{self, Command, Outputs, Worker};
+use crate::{Config, Event, Result};
+use crate::{bat, cpu, datetime, freq, load, mem, net, thermal, uptime, users, weather};
+
+/// A single data source of the status line, such as the cpu usage or the clock.
+///
+/// Implement this for in-house blocks and add them to a `Registry` to make
+/// their placeholders available in `format`. Every block is updated on its
+/// own thread, so `update` is free to block.
+pub trait Block: Send {
+    /// Builds the block from the user's config.
+    fn init(config: &Config) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Reads the data source and refreshes the block's value.
+    fn update(&mut self);
+
+    /// Renders the value of `placeholder`, `None` if it is unavailable.
+    ///
+    /// `args` are the block specific options of the placeholder, such as
+    /// `unit` in `{download:unit=KiB}`.
+    fn output(&self, placeholder: &str, args: &Args) -> Option<String>;
+
+    /// Runs `action`, configured in the block's `on_click` table, for a click on `placeholder`.
+    ///
+    /// The block's output is shown again afterwards, so actions can switch
+    /// what the block renders.
+    fn action(&mut self, _placeholder: &str, action: &str) -> Result<()> {
+        Err(format!("unknown action `{}`", action).into())
+    }
+
+    /// Checks the options of `placeholder` before the block starts updating.
+    fn check_args(&self, _placeholder: &str, _args: &Args) -> Result<()> {
+        Ok(())
+    }
+
+    /// How often the block wants to be updated, `None` to follow `update_interval`.
+    ///
+    /// An `interval` in the block's config table takes precedence over this.
+    fn interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// How long from now until the output of `placeholders` changes next,
+    /// for blocks that know, like a clock. The block is then updated
+    /// right at that moment instead of every interval, but at least once a
+    /// minute in case the system clock jumps.
+    ///
+    /// An `interval` in the block's config table takes precedence over this.
+    fn until_change(&self, _placeholders: &[(&str, &Args)]) -> Option<Duration> {
+        None
+    }
+
+    /// Every placeholder the block renders, like `bat.status`, for
+    /// `--list-blocks`. Those taking a name or number have it in capitals,
+    /// like `cpu.N`. Empty if there are none besides the registered ones.
+    fn placeholders() -> &'static [&'static str]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+
+    /// Keys the block reads from its config table, besides `interval`,
+    /// `icon`, `color`, `thresholds`, `on_click` and `signal`, which every
+    /// block has. Any other key is rejected as a typo.
+    fn config_keys() -> &'static [&'static str]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+}
+
+type Init = fn(&Config) -> Result<Box<dyn Block>>;
+
+fn init_boxed<B: Block + 'static>(config: &Config) -> Result<Box<dyn Block>> {
+    Ok(Box::new(B::init(config)?))
+}
+
+struct Entry {
+    name: String,
+    placeholders: Vec<String>,
+    /// What `Block::placeholders` lists.
+    listed: &'static [&'static str],
+    keys: &'static [&'static str],
+    init: Init,
+}
+
+/// Maps placeholder names to the blocks that render them.
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    /// An empty registry, without any of the builtin blocks.
+    pub fn new() -> Registry {
+        Registry { entries: Vec::new() }
+    }
+
+    /// Registers block `B` under `name`, rendering each of `placeholders`.
+    ///
+    /// A placeholder `clock` also takes instances like `{clock.tokyo}`,
+    /// which the block tells apart by the full name it gets in `output`.
+    ///
+    /// Registering a name twice replaces the earlier block.
+    pub fn register<B: Block + 'static>(&mut self, name: &str, placeholders: &[&str]) {
+        self.entries.retain(|e| e.name != name);
+        self.entries.push(Entry {
+            name: name.to_string(),
+            placeholders: placeholders.iter().map(|p| p.to_string()).collect(),
+            listed: B::placeholders(),
+            keys: B::config_keys(),
+            init: init_boxed::<B>,
+        });
+    }
+
+    /// Names of the registered blocks together with every placeholder they
+    /// render, as listed by `Block::placeholders`.
+    pub fn blocks(&self) -> impl Iterator<Item = (&str, Vec<&str>)> {
+        self.entries.iter().map(|e| {
+            let placeholders = if e.listed.is_empty() {
+                e.placeholders.iter().map(|p| p.as_str()).collect()
+            } else {
+                e.listed.to_vec()
+            };
+            (e.name.as_str(), placeholders)
+        })
+    }
+
+    /// Initializes every block that has a placeholder in `format` and starts
+    /// updating them, sending `Event::Changed` to `events` on new output.
+    pub(crate) fn init(&self, config: &Config, format: &Format, events: &Sender<Event>) -> Result<Blocks> {
+        let (mut blocks, workers) = self.prepare(config, format)?;
+
+        if workers.is_empty() {
+            // nothing will ever change, draw the format once
+            let _ = events.send(Event::Changed);
+        }
+
+        for w in workers {
+            let (tx, commands) = channel();
+            blocks.workers.insert(w.name.clone(), tx);
+            worker::spawn(w, blocks.outputs.clone(), events.clone(), commands);
+        }
+
+        Ok(blocks)
+    }
+
+    /// Like `init`, but updates every block once on this thread instead of
+    /// starting workers.
+    ///
+    /// Blocks still without output, like the cpu usage or the download speed
+    /// which are rates, are updated a second time a moment later.
+    pub(crate) fn init_once(&self, config: &Config, format: &Format) -> Result<Blocks> {
+        let (blocks, mut workers) = self.prepare(config, format)?;
+        for w in &mut workers {
+            w.update_once(&blocks.outputs);
+        }
+
+        workers.retain(|w| !w.has_output(&blocks.outputs));
+        if let Some(wait) = workers.iter().map(|w| w.interval.min(Duration::from_secs(1))).max() {
+            thread::sleep(wait);
+            for w in &mut workers {
+                w.update_once(&blocks.outputs);
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Checks `format` and the block tables of `config` without updating any
+    /// block.
+    pub(crate) fn check(&self, config: &Config, format: &Format) -> Result<()> {
+        self.prepare(config, format)?;
+        Ok(())
+    }
+
+    /// Initializes the blocks of `format` without starting them.
+    fn prepare(&self, config: &Config, format: &Format) -> Result<(Blocks, Vec<Worker>)> {
+        let schema: Vec<_> = self.entries.iter().map(|e| (e.name.as_str(), e.keys)).collect();
+        schema::validate(config, &schema)?;
+
+        let format_error = |p: &Placeholder, kind| {
+            let e = FormatError::new(format.as_str(), p.column(), kind);
+            config.error_at(&["format"], e.to_string())
+        };
+        let mut used: Vec<Vec<Placeholder>> = self.entries.iter().map(|_| Vec::new()).collect();
+
+        for p in format.placeholders() {
+            p.check_args()
+                .map_err(|e| format_error(p, ErrorKind::InvalidArgs(p.name().to_string(), e)))?;
+
+            let i = self
+                .entries
+                .iter()
+                .position(|e| e.placeholders.iter().any(|name| matches(name, p.name())))
+                .ok_or_else(|| {
+                    let known: Vec<_> = self.entries.iter().flat_map(|e| &e.placeholders).map(|p| p.as_str()).collect();
+                    let hint = schema::suggest(p.name(), known.iter());
+                    format_error(p, ErrorKind::UnknownPlaceholder(p.name().to_string(), hint))
+                })?;
+            used[i].push(p.clone());
+        }
+
+        let mut workers = Vec::new();
+        let mut names = HashMap::new();
+        for (entry, used) in self.entries.iter().zip(used) {
+            if used.is_empty() {
+                continue;
+            }
+
+            let block = (entry.init)(config)?;
+            for p in &used {
+                block
+                    .check_args(p.name(), p.args())
+                    .map_err(|e| format_error(p, ErrorKind::InvalidArgs(p.name().to_string(), e.to_string())))?;
+            }
+            let fixed_interval = config.block_secs(&entry.name, "interval")?;
+            let interval = match fixed_interval {
+                Some(i) => i,
+                None => block.interval().unwrap_or_else(|| config.update_interval()),
+            };
+
+            for p in &used {
+                names.insert(p.name().to_string(), entry.name.clone());
+            }
+            workers.push(Worker {
+                name: entry.name.clone(),
+                block,
+                interval,
+                fixed_interval: fixed_interval.is_some(),
+                style: Style::new(config, &entry.name)?,
+                placeholders: used,
+            });
+        }
+
+        let mut blocks = Blocks {
+            outputs: Arc::new(Mutex::new(HashMap::new())),
+            names,
+            signals: HashMap::new(),
+            workers: HashMap::new(),
+        };
+
+        if config.statuscmd() {
+            let names: Vec<_> = workers.iter().map(|w| w.name.as_str()).collect();
+            blocks.signals = assign_signals(config, &names)?;
+        }
+
+        Ok((blocks, workers))
+    }
+}
+
+/// Whether placeholder `name` in the format is the registered `placeholder`
+/// or one of its instances, `placeholder.INSTANCE`.
+fn matches(placeholder: &str, name: &str) -> bool {
+    match name.strip_prefix(placeholder) {
+        Some(rest) => rest.is_empty() || rest.starts_with('.'),
+        None => false,
+    }
+}
+
+/// Checks that `placeholder` is one of the `known` placeholders of a block,
+/// for `Block::check_args`.
+pub(crate) fn check_placeholder(placeholder: &str, known: &[&str]) -> Result<()> {
+    match known {
+        _ if known.contains(&placeholder) => Ok(()),
+        [only] => Err(format!("expected `{}`", only).into()),
+        _ => {
+            let list: Vec<_> = known.iter().map(|p| format!("`{}`", p)).collect();
+            Err(format!("expected one of {}{}", list.join(", "), schema::suggest(placeholder, known.iter())).into())
+        }
+    }
+}
+
+/// Gives every block a statuscmd marker byte, the `signal` in its table or the
+/// lowest one left.
+fn assign_signals(config: &Config, names: &[&str]) -> Result<HashMap<String, u8>> {
+    let mut signals = HashMap::new();
+    let mut taken = Vec::new();
+
+    for name in names {
+        if let Some(n) = config.block_int(name, "signal")? {
+            if n < 1 || n > i64::from(MAX_SIGNAL) {
+                let msg = format!("`{}.signal` must be between 1 and {}", name, MAX_SIGNAL);
+                return Err(config.error_at(&[name, "signal"], msg));
+            }
+            if taken.contains(&(n as u8)) {
+                let msg = format!("`{}.signal` {} is used by another block", name, n);
+                return Err(config.error_at(&[name, "signal"], msg));
+            }
+            taken.push(n as u8);
+            signals.insert(name.to_string(), n as u8);
+        }
+    }
+
+    for name in names {
+        if signals.contains_key(*name) {
+            continue;
+        }
+        let n = (1..=MAX_SIGNAL)
+            .find(|n| !taken.contains(n))
+            .ok_or("too many blocks for statuscmd")?;
+        taken.push(n);
+        signals.insert(name.to_string(), n);
+    }
+
+    Ok(signals)
+}
+
+impl Default for Registry {
+    /// A registry with all the builtin blocks.
+    fn default() -> Registry {
+        let mut r = Registry::new();
+        r.register::<datetime::Time>("datetime", &["datetime", "clock"]);
+        r.register::<weather::Weather>("weather", &["weather"]);
+        r.register::<net::Net>("net", &["download", "upload"]);
+        r.register::<cpu::Cpu>("cpu", &["cpu"]);
+        r.register::<thermal::Thermal>("thermal", &["temp"]);
+        r.register::<freq::Freq>("freq", &["freq"]);
+        r.register::<load::Load>("load", &["load"]);
+        r.register::<uptime::Uptime>("uptime", &["uptime"]);
+        r.register::<users::Users>("users", &["users"]);
+        r.register::<mem::Mem>("memory", &["memory"]);
+        r.register::<bat::Battery>("bat", &["bat"]);
+        r
+    }
+}
+
+/// The blocks used by the current `format`, each updating on its own thread.
+pub(crate) struct Blocks {
+    outputs: Outputs,
+    // block name of every placeholder
+    names: HashMap<String, String>,
+    // statuscmd marker byte of every block
+    signals: HashMap<String, u8>,
+    // stops the workers when dropped
+    workers: HashMap<String, Sender<Command>>,
+}
+
+impl Blocks {
+    /// The `on_click` actions of the running blocks.
+    pub(crate) fn actions(&self, config: &Config) -> Actions {
+        let signals = self.signals.iter().map(|(name, &n)| (n, name.to_string())).collect();
+        Actions::new(config, self.workers.clone(), signals)
+    }
+
+    /// Fills every placeholder in `format` with the latest output of its block.
+    pub(crate) fn render(&self, format: &Format) -> Vec<Segment> {
+        let outputs = worker::lock(&self.outputs);
+
+        let mut segments = Vec::new();
+        self.render_tokens(format.tokens(), &outputs, &mut segments);
+        segments
+    }
+
+    fn render_tokens(
+        &self,
+        tokens: &[Token],
+        outputs: &HashMap<String, Option<String>>,
+        segments: &mut Vec<Segment>,
+    ) {
+        for t in tokens {
+            match t {
+                Token::Text(text) => push_text(segments, text),
+                Token::Placeholder(p) => {
+                    let text = match outputs.get(p.key()) {
+                        Some(Some(out)) => out.to_string(),
+                        Some(None) => String::new(),
+                        None => String::from("N/A"),
+                    };
+                    let name = self.names.get(p.name());
+                    segments.push(Segment {
+                        text,
+                        name: name.cloned(),
+                        instance: Some(p.name().to_string()),
+                        signal: name.and_then(|n| self.signals.get(n)).cloned(),
+                    });
+                }
+                Token::Group(group) => {
+                    // a group is hidden together with its text unless all of its own
+                    // placeholders have a value, nested groups decide for themselves
+                    let shown = group.iter().all(|t| match t {
+                        Token::Placeholder(p) => matches!(outputs.get(p.key()), Some(Some(_))),
+                        _ => true,
+                    });
+                    if shown {
+                        self.render_tokens(group, outputs, segments);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn push_text(segments: &mut Vec<Segment>, text: &str) {
+    match segments.last_mut() {
+        Some(last) if last.name.is_none() => last.text.push_str(text),
+        _ => segments.push(Segment {
+            text: text.to_string(),
+            name: None,
+            instance: None,
+            signal: None,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders `format` with `outputs` as the workers would have left them.
+    fn render(format: &str, outputs: &[(&str, Option<&str>)]) -> String {
+        let outputs = outputs.iter().map(|(k, v)| (k.to_string(), v.map(|v| v.to_string()))).collect();
+        let blocks = Blocks {
+            outputs: Arc::new(Mutex::new(outputs)),
+            names: HashMap::new(),
+            signals: HashMap::new(),
+            workers: HashMap::new(),
+        };
+        let segments = blocks.render(&Format::parse(format).unwrap());
+        segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn missing_and_hidden_outside_groups() {
+        // never had output, and hidden by `min`
+        assert_eq!(render("{a} {b:min=1} {c}", &[("b:min=1", None), ("c", Some("3"))]), "N/A  3");
+    }
+
+    #[test]
+    fn groups_hide_with_their_text() {
+        let format = "a[ b{x}[ c{y}] d] e";
+        assert_eq!(render(format, &[("x", Some("1")), ("y", Some("2"))]), "a b1 c2 d e");
+        // the nested group goes alone
+        assert_eq!(render(format, &[("x", Some("1"))]), "a b1 d e");
+        // and with the outer one
+        assert_eq!(render(format, &[("y", Some("2"))]), "a e");
+        assert_eq!(render(format, &[("x", None), ("y", Some("2"))]), "a e");
+    }
+
+    #[test]
+    fn groups_without_placeholders_stay() {
+        assert_eq!(render("[a][{x}]b", &[]), "ab");
+    }
+
+    /// Like the cpu usage, a value only from the second update on.
+    struct Rate {
+        updates: u32,
+    }
+
+    impl Block for Rate {
+        fn init(_config: &Config) -> Result<Rate> {
+            Ok(Rate { updates: 0 })
+        }
+
+        fn update(&mut self) {
+            self.updates += 1;
+        }
+
+        fn output(&self, _placeholder: &str, _args: &Args) -> Option<String> {
+            Some(self.updates.to_string()).filter(|_| self.updates > 1)
+        }
+
+        fn interval(&self) -> Option<Duration> {
+            Some(Duration::from_millis(10))
+        }
+    }
+
+    #[test]
+    fn once_samples_rates_twice() {
+        let mut registry = Registry::new();
+        registry.register::<Rate>("rate", &["rate"]);
+        let format = Format::parse("{rate}").unwrap();
+        let blocks = registry.init_once(&Config::default(), &format).unwrap();
+        assert_eq!(blocks.render(&format)[0].text, "2");
+    }
+
+    #[test]
+    fn unknown_sub_placeholders_get_a_hint() {
+        let known = ["bat", "bat.status", "bat.time"];
+        assert!(check_placeholder("bat.time", &known).is_ok());
+        assert_eq!(
+            check_placeholder("bat.stauts", &known).unwrap_err().to_string(),
+            "expected one of `bat`, `bat.status`, `bat.time`, did you mean `bat.status`?"
+        );
+        assert_eq!(
+            check_placeholder("load.avg", &["load.1", "load.5"]).unwrap_err().to_string(),
+            "expected one of `load.1`, `load.5`"
+        );
+    }
+
+    #[test]
+    fn lists_sub_placeholders() {
+        let registry = Registry::default();
+        let blocks: HashMap<_, _> = registry.blocks().collect();
+        assert_eq!(blocks["datetime"], ["datetime", "clock.NAME"]);
+        assert!(blocks["cpu"].contains(&"cpu.N"));
+        assert!(blocks["bat"].contains(&"bat.status"));
+        // without a list of its own, what it is registered with
+        assert_eq!(blocks["net"], ["download", "upload"]);
+    }
+
+    #[test]
+    fn a_single_placeholder_needs_no_hint() {
+        assert!(check_placeholder("uptime", &["uptime"]).is_ok());
+        assert_eq!(check_placeholder("uptime.x", &["uptime"]).unwrap_err().to_string(), "expected `uptime`");
+    }
+
+    #[test]
+    fn placeholder_matches_instances() {
+        assert!(matches("clock", "clock"));
+        assert!(matches("clock", "clock.tokyo"));
+        assert!(!matches("clock", "clocks"));
+        assert!(!matches("cpu", "cp"));
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::process::Command as Process;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::worker::{lock, Command};
+use crate::{Config, Result};
+
+// [weather.on_click]
+// left = "refresh"
+// right = "exec:xdg-open https://openweathermap.org"
+//
+// [datetime.on_click]
+// scroll_up = "toggle_format"
+
+/// Mouse buttons as named in the `on_click` tables, with their X button number.
+pub(crate) const BUTTONS: &[(&str, u8)] = &[
+    ("left", 1),
+    ("middle", 2),
+    ("right", 3),
+    ("scroll_up", 4),
+    ("scroll_down", 5),
+];
+
+/// What a click on a block does.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Action {
+    /// `refresh` updates the block right away.
+    Refresh,
+    /// `exec:CMD` runs CMD with `sh -c`.
+    Exec(String),
+    /// Anything else is passed to `Block::action`.
+    Block(String),
+}
+
+impl Action {
+    fn parse(s: &str) -> Action {
+        if s == "refresh" {
+            Action::Refresh
+        } else if let Some(cmd) = s.strip_prefix("exec:") {
+            Action::Exec(cmd.to_string())
+        } else {
+            Action::Block(s.to_string())
+        }
+    }
+}
+
+/// A click on a block, as reported by i3bar or dwm.
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub(crate) struct Click {
+    pub name: Option<String>,
+    pub instance: Option<String>,
+    pub button: u8,
+}
+
+/// The `on_click` actions of every block, and the workers to send them to.
+#[derive(Default)]
+pub(crate) struct Actions {
+    actions: HashMap<(String, u8), Action>,
+    workers: HashMap<String, Sender<Command>>,
+    signals: HashMap<u8, String>,
+}
+
+/// The actions of the running config, swapped out when the config is reloaded.
+pub(crate) type SharedActions = Arc<Mutex<Actions>>;
+
+impl Actions {
+    /// Reads the `on_click` tables of the blocks in `workers`.
+    ///
+    /// `signals` maps statuscmd marker bytes to block names.
+    pub(crate) fn new(
+        config: &Config,
+        workers: HashMap<String, Sender<Command>>,
+        signals: HashMap<u8, String>,
+    ) -> Actions {
+        let mut actions = HashMap::new();
+
+        for name in workers.keys() {
+            // checked by `schema::validate`
+            let table = match config.block(name).and_then(|t| t.get("on_click")).and_then(|t| t.as_table()) {
+                Some(t) => t,
+                None => continue,
+            };
+
+            for (button, action) in table {
+                let number = BUTTONS.iter().find(|(b, _)| b == button).map(|(_, n)| *n);
+                if let (Some(number), Some(action)) = (number, action.as_str()) {
+                    actions.insert((name.to_string(), number), Action::parse(action));
+                }
+            }
+        }
+
+        Actions {
+            actions,
+            workers,
+            signals,
+        }
+    }
+
+    /// Name of the block with statuscmd marker `n`.
+    pub(crate) fn signal_block(&self, n: u8) -> Option<&str> {
+        self.signals.get(&n).map(|s| s.as_str())
+    }
+
+    /// Runs the action configured for `click`, if any.
+    pub(crate) fn handle(&self, click: &Click) {
+        let name = match &click.name {
+            Some(n) => n,
+            None => return,
+        };
+        let action = match self.actions.get(&(name.to_string(), click.button)) {
+            Some(a) => a,
+            None => return,
+        };
+        let instance = click.instance.clone().unwrap_or_else(|| name.to_string());
+
+        let command = match action {
+            Action::Refresh => Command::Refresh,
+            Action::Block(action) => Command::Action {
+                placeholder: instance,
+                action: action.to_string(),
+            },
+            Action::Exec(cmd) => {
+                let env = [
+                    ("BLOCK_NAME", name.to_string()),
+                    ("BLOCK_INSTANCE", instance),
+                    ("BLOCK_BUTTON", click.button.to_string()),
+                ];
+                exec(cmd, &env);
+                return;
+            }
+        };
+
+        if let Some(worker) = self.workers.get(name) {
+            let _ = worker.send(command);
+        }
+    }
+}
+
+/// Runs `cmd` with `sh -c` and the variables of `env` in the background.
+pub(crate) fn exec(cmd: &str, env: &[(&str, String)]) {
+    let child = Process::new("sh").arg("-c").arg(cmd).envs(env.iter().cloned()).spawn();
+
+    match child {
+        // reap it without holding up the caller
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => eprintln!("Error: could not run `{}`: {}", cmd, e),
+    }
+}
+
+/// Parses a line of the i3bar click event stream.
+///
+/// The stream is an infinite JSON array, so the opening `[` and blank lines
+/// give `None` and the `,` before every event but the first is skipped.
+pub(crate) fn parse_click(line: &str) -> Result<Option<Click>> {
+    let line = line.trim().trim_start_matches(',').trim_start();
+    if line.is_empty() || line == "[" {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(line)?))
+}
+
+/// Reads click events from `input`, usually stdin, until it closes.
+pub(crate) fn listen<R: BufRead>(input: R, actions: &SharedActions) {
+    for line in input.lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("Error: reading click events: {}", e);
+                return;
+            }
+        };
+
+        match parse_click(&line) {
+            Ok(Some(click)) => lock(actions).handle(&click),
+            Ok(None) => {}
+            Err(e) => eprintln!("Error: invalid click event `{}`: {}", line, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn listen_sends_commands_to_workers() {
+        let config = Config::parse(
+            "[cpu.on_click]\nleft = \"refresh\"\n\n[datetime.on_click]\nright = \"toggle_format\"\n",
+        ).unwrap();
+        let (cpu, cpu_commands) = channel();
+        let (datetime, datetime_commands) = channel();
+        let workers = vec![(String::from("cpu"), cpu), (String::from("datetime"), datetime)];
+        let actions = Actions::new(&config, workers.into_iter().collect(), HashMap::new());
+        let actions = Arc::new(Mutex::new(actions));
+
+        let input = r#"[
+{"name":"cpu","instance":"cpu","button":1}
+,{"name":"datetime","instance":"clock.tokyo","button":3,"x":120}
+{"name":
+,{"name":"cpu","instance":"cpu","button":2}
+{"name":"weather","button":1}
+"#;
+        listen(Cursor::new(input), &actions);
+
+        assert_eq!(cpu_commands.try_recv(), Ok(Command::Refresh));
+        // no action for the middle button
+        assert!(cpu_commands.try_recv().is_err());
+        assert_eq!(
+            datetime_commands.try_recv(),
+            Ok(Command::Action {
+                placeholder: String::from("clock.tokyo"),
+                action: String::from("toggle_format"),
+            })
+        );
+        assert!(datetime_commands.try_recv().is_err());
+    }
+
+    #[test]
+    fn parses_the_event_stream() {
+        assert!(parse_click("[").unwrap().is_none());
+        assert!(parse_click("  ").unwrap().is_none());
+        let click = parse_click(r#",{"name":"bat","button":4}"#).unwrap().unwrap();
+        assert_eq!((click.name.as_deref(), click.instance, click.button), (Some("bat"), None, 4));
+        assert!(parse_click(r#"{"name":"bat"}"#).is_err());
+    }
+}
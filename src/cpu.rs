@@ -1,71 +1,300 @@
 use cpuerror::*;
+use std::collections::VecDeque;
 use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct Cpu {
-    val: std::result::Result<i32, CpuError>,
-    system: i32,
-    last_sum: i32,
+use crate::sys::Fs;
+use crate::{Args, Block, Config};
+
+// {cpu}         usage of all cores together, in percent
+// {cpu.N}       usage of core N, counting from 0
+// {cpu.max}     usage of the busiest core
+// {cpu.user}, {cpu.system}, {cpu.iowait}, {cpu.steal}, {cpu.idle}
+//               what all cores spent their time on, in percent
+// {cpu.graph}   sparkline of {cpu} over the last `history` updates
+// {cpu.cores}   one bar per core, all high when a build keeps them busy
+//
+// [cpu]
+// history = 10
+// glyphs = "▁▂▃▄▅▆▇█"    # lowest to highest, e.g. Siji's bar glyphs
+
+/// `N` stands for any core number, as `check_args` takes it.
+const PLACEHOLDERS: &[&str] = &[
+    "cpu",
+    "cpu.N",
+    "cpu.max",
+    "cpu.user",
+    "cpu.system",
+    "cpu.iowait",
+    "cpu.steal",
+    "cpu.idle",
+    "cpu.graph",
+    "cpu.cores",
+];
+
+const DEFAULT_GLYPHS: &str = "▁▂▃▄▅▆▇█";
+
+const BREAKDOWN: &[&str] = &["user", "system", "iowait", "steal", "idle"];
+
+/// The jiffies of one `cpu` line of `/proc/stat`, grouped.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+struct Times {
+    /// user and nice, which include guest and guest_nice
+    user: u64,
+    /// system, irq and softirq
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    steal: u64,
 }
 
-impl Cpu {
-    pub fn init() -> Cpu {
-        Cpu {
-            val: Ok(0),
-            system: 0,
-            last_sum: 0,
+impl Times {
+    //      user    nice   system  idle      iowait irq   softirq  steal  guest  guest_nice
+    // cpu  74608   2520   24433   1117073   6176   4054  0        0      0      0
+    fn parse(fields: &[u64]) -> Option<Times> {
+        let field = |i: usize| fields.get(i).copied();
+        Some(Times {
+            user: field(0)? + field(1)?,
+            system: field(2)? + field(5).unwrap_or(0) + field(6).unwrap_or(0),
+            idle: field(3)?,
+            // kernels before 2.6 have only the first four
+            iowait: field(4).unwrap_or(0),
+            steal: field(7).unwrap_or(0),
+        })
+    }
+
+    /// The time spent since `last`, `None` if the counters went backwards,
+    /// as they do when a core comes back online.
+    fn since(&self, last: &Times) -> Option<Times> {
+        Some(Times {
+            user: self.user.checked_sub(last.user)?,
+            system: self.system.checked_sub(last.system)?,
+            idle: self.idle.checked_sub(last.idle)?,
+            iowait: self.iowait.checked_sub(last.iowait)?,
+            steal: self.steal.checked_sub(last.steal)?,
+        })
+    }
+
+    fn total(&self) -> u64 {
+        self.user + self.system + self.idle + self.iowait + self.steal
+    }
+}
+
+/// How the time between two samples was spent, in percent.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+struct Usage {
+    user: f64,
+    system: f64,
+    idle: f64,
+    iowait: f64,
+    steal: f64,
+}
+
+impl Usage {
+    /// `None` when no time passed.
+    fn of(delta: &Times) -> Option<Usage> {
+        let total = delta.total();
+        if total == 0 {
+            return None;
         }
+        let percent = |jiffies: u64| jiffies as f64 * 100.0 / total as f64;
+        Some(Usage {
+            user: percent(delta.user),
+            system: percent(delta.system),
+            idle: percent(delta.idle),
+            iowait: percent(delta.iowait),
+            steal: percent(delta.steal),
+        })
     }
 
-    pub fn update(&mut self) {
-        //      user    nice   system  idle      iowait irq   softirq  steal  guest  guest_nice
-        // cpu  74608   2520   24433   1117073   6176   4054  0        0      0      0
+    /// Everything but waiting, for nothing or for the disk.
+    fn busy(&self) -> f64 {
+        100.0 - self.idle - self.iowait
+    }
 
+    fn get(&self, name: &str) -> Option<f64> {
+        match name {
+            "user" => Some(self.user),
+            "system" => Some(self.system),
+            "idle" => Some(self.idle),
+            "iowait" => Some(self.iowait),
+            "steal" => Some(self.steal),
+            _ => None,
+        }
+    }
+}
+
+/// A `cpu` line of `/proc/stat` over time.
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Core {
+    /// `None` until the second sample.
+    usage: Option<Usage>,
+    last: Option<Times>,
+}
+
+impl Core {
+    /// Takes a sample, returns the new usage if there is one.
+    fn sample(&mut self, times: Times) -> Option<Usage> {
+        // a core that didn't count anything keeps its last usage
+        if self.last == Some(times) {
+            return None;
+        }
+        self.usage = self.last.and_then(|last| Usage::of(&times.since(&last)?));
+        self.last = Some(times);
+        self.usage
+    }
+}
+
+/// The lines of `/proc/stat` that start with `cpu`.
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Stat {
+    total: Times,
+    /// By core number, which has gaps when cores are offline.
+    cores: Vec<(usize, Times)>,
+}
+
+#[derive(Debug)]
+pub struct Cpu {
+    total: Core,
+    /// By core number, `None` for offline cores.
+    cores: Vec<Option<Core>>,
+    history: VecDeque<f64>,
+    history_len: usize,
+    glyphs: Vec<char>,
+    fs: Arc<dyn Fs>,
+}
+
+impl Block for Cpu {
+    fn init(config: &Config) -> crate::Result<Cpu> {
+        let history_len = match config.block_int("cpu", "history")? {
+            Some(n) if n < 1 => return Err(config.error_at(&["cpu", "history"], "`cpu.history` must be at least 1")),
+            Some(n) => n as usize,
+            None => 10,
+        };
+
+        let glyphs: Vec<char> = config.block_str("cpu", "glyphs")?.unwrap_or(DEFAULT_GLYPHS).chars().collect();
+        if glyphs.is_empty() {
+            return Err(config.error_at(&["cpu", "glyphs"], "`cpu.glyphs` must not be empty"));
+        }
+
+        Ok(Cpu {
+            total: Core::default(),
+            cores: Vec::new(),
+            history: VecDeque::with_capacity(history_len),
+            history_len,
+            glyphs,
+            fs: config.fs(),
+        })
+    }
+
+    fn update(&mut self) {
         // explanation for this shit
         // https://www.idnt.net/en-GB/kb/941772
-        match read_cpu_proc() {
-            Ok(cpu) =>  {
-                let cpu_sum: i32 = cpu.iter().sum();
-
-                if let Some(s) = cpu.get(3) {
-                    let cpu_delta = cpu_sum - self.last_sum;
-                    let cpu_idle = s - self.system;
-                    let cpu_used = cpu_delta - cpu_idle;
-                    let cpu_usage = 100 * cpu_used / cpu_delta;
-
-                    self.system = *s;
-                    self.last_sum = cpu_sum;
-
-                    self.val = Ok(cpu_usage);
-                } else {
-                    eprintln!("Error: could get value from `/proc/stat`");
-                }
-            },
+        match read_cpu_proc(&*self.fs) {
+            Ok(stat) => self.sample(&stat),
             Err(e) => {
                 eprintln!("Error: `/proc/stat` {}", e);
-                self.val = Err(CpuError::Generic);
+                self.total = Core::default();
+                self.cores.clear();
             }
         }
     }
 
-    pub fn output(&self) -> Option<String> {
-        match self.val {
-            Ok(i) => Some(format!("{:02}", i)),
-            Err(_) => None,
+    fn output(&self, placeholder: &str, _args: &Args) -> Option<String> {
+        let usage = match placeholder {
+            "cpu" => self.total.usage?.busy(),
+            "cpu.max" => self.cores().map(|u| u.busy()).max_by(f64::total_cmp)?,
+            "cpu.graph" => return Some(self.history.iter().map(|&u| self.glyph(u)).collect()),
+            "cpu.cores" => return Some(self.cores().map(|u| self.glyph(u.busy())).collect()),
+            _ => {
+                let name = placeholder.strip_prefix("cpu.")?;
+                match name.parse::<usize>() {
+                    Ok(n) => self.cores.get(n)?.as_ref()?.usage?.busy(),
+                    Err(_) => self.total.usage?.get(name)?,
+                }
+            }
+        };
+        Some(format!("{:02.0}", usage))
+    }
+
+    fn check_args(&self, placeholder: &str, _args: &Args) -> crate::Result<()> {
+        match placeholder.strip_prefix("cpu.") {
+            None | Some("max") | Some("graph") | Some("cores") => Ok(()),
+            Some(n) if n.parse::<usize>().is_ok() || BREAKDOWN.contains(&n) => Ok(()),
+            Some(_) => {
+                let breakdown: Vec<_> = BREAKDOWN.iter().map(|b| format!("`cpu.{}`", b)).collect();
+                Err(format!(
+                    "expected one of `cpu`, `cpu.N` with a core number N, `cpu.max`, {}, `cpu.graph`, `cpu.cores`",
+                    breakdown.join(", ")
+                )
+                .into())
+            }
         }
     }
+
+    fn placeholders() -> &'static [&'static str] {
+        PLACEHOLDERS
+    }
+
+    fn config_keys() -> &'static [&'static str] {
+        &["history", "glyphs"]
+    }
 }
 
-fn read_cpu_proc() -> Result<Vec<i32>, Box<dyn Error>> {
-    let cpu = std::fs::read_to_string("/proc/stat")?
-        .split('\n')
-        .nth(0)
-        .ok_or_else(|| CpuError::ReadProc)?
-        .split_whitespace()
-        .filter_map(|s| s.parse::<i32>().ok())
-        .collect::<Vec<i32>>();
+impl Cpu {
+    fn sample(&mut self, stat: &Stat) {
+        if let Some(usage) = self.total.sample(stat.total) {
+            if self.history.len() == self.history_len {
+                self.history.pop_front();
+            }
+            self.history.push_back(usage.busy());
+        }
 
-    Ok(cpu)
+        // a core that went offline starts over when it is back
+        let mut cores = vec![None; stat.cores.iter().map(|(n, _)| n + 1).max().unwrap_or(0)];
+        for (n, times) in &stat.cores {
+            let mut core = self.cores.get_mut(*n).and_then(|c| c.take()).unwrap_or_default();
+            core.sample(*times);
+            cores[*n] = Some(core);
+        }
+        self.cores = cores;
+    }
+
+    /// The usage of the online cores that have one.
+    fn cores(&self) -> impl Iterator<Item = Usage> + '_ {
+        self.cores.iter().flatten().filter_map(|c| c.usage)
+    }
+
+    /// The glyph of `usage`, from the lowest for 0% to the highest for 100%.
+    fn glyph(&self, usage: f64) -> char {
+        let last = (self.glyphs.len() - 1) as f64;
+        self.glyphs[(usage.clamp(0.0, 100.0) * last / 100.0).round() as usize]
+    }
+}
+
+fn read_cpu_proc(fs: &dyn Fs) -> Result<Stat, Box<dyn Error>> {
+    let mut total = None;
+    let mut cores = Vec::new();
+    for line in fs.read(Path::new("/proc/stat"))?.lines() {
+        let mut fields = line.split_whitespace();
+        let core = match fields.next().and_then(|name| name.strip_prefix("cpu")) {
+            Some(core) => core,
+            None => continue,
+        };
+        let fields = fields.map(|s| s.parse()).collect::<Result<Vec<u64>, _>>()?;
+        let times = Times::parse(&fields).ok_or(CpuError::ReadProc)?;
+        if core.is_empty() {
+            total = Some(times);
+        } else {
+            cores.push((core.parse()?, times));
+        }
+    }
+
+    Ok(Stat {
+        total: total.ok_or(CpuError::ReadProc)?,
+        cores,
+    })
 }
 
 mod cpuerror {
@@ -74,14 +303,12 @@ mod cpuerror {
     #[derive(Debug, PartialEq, Clone)]
     pub enum CpuError {
         ReadProc,
-        Generic,
     }
 
     impl std::error::Error for CpuError {
         fn description(&self) -> &str {
             match *self {
                 CpuError::ReadProc => "failed parsing `/proc/stat`",
-                CpuError::Generic => "generic error",
             }
         }
     }
@@ -90,8 +317,179 @@ mod cpuerror {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match *self {
                 CpuError::ReadProc => f.write_str("failed parsing `/proc/stat`"),
-                CpuError::Generic => f.write_str("generic error"),
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::{fixture, sampled, MockFs};
+
+    fn output(cpu: &Cpu, placeholder: &str) -> Option<String> {
+        cpu.output(placeholder, &Args::default())
+    }
+
+    #[test]
+    fn reads_every_cpu_line() {
+        let stat = read_cpu_proc(fixture("laptop").as_ref()).unwrap();
+        let total = Times {
+            user: 1093214 + 2871,
+            system: 301465 + 12877,
+            idle: 18274553,
+            iowait: 41233,
+            steal: 0,
+        };
+        assert_eq!(stat.total, total);
+        assert_eq!(stat.cores.iter().map(|(n, _)| *n).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(stat.cores[3].1.idle, 4572082);
+    }
+
+    #[test]
+    fn reads_counters_past_32_bits() {
+        let stat = read_cpu_proc(fixture("server").as_ref()).unwrap();
+        assert_eq!(stat.total.idle, 60727519922);
+        assert_eq!(stat.total.steal, 211394713);
+        assert_eq!(stat.cores.len(), 64);
+    }
+
+    #[test]
+    fn no_usage_before_the_second_sample() {
+        let fs = Arc::new(MockFs::default());
+        let mut cpu: Cpu = sampled("", fs.clone());
+
+        fs.set("/proc/stat", "cpu  100 0 100 800 0 0 0 0 0 0\ncpu0 100 0 100 800 0 0 0 0 0 0\n");
+        cpu.update();
+        assert_eq!(output(&cpu, "cpu"), None);
+        assert_eq!(output(&cpu, "cpu.graph").as_deref(), Some(""));
+
+        // 300 busy and 100 idle jiffies later
+        fs.set("/proc/stat", "cpu  300 0 200 900 0 0 0 0 0 0\ncpu0 300 0 200 900 0 0 0 0 0 0\n");
+        cpu.update();
+        assert_eq!(output(&cpu, "cpu").as_deref(), Some("75"));
+    }
+
+    #[test]
+    fn breakdown_of_the_time() {
+        let fs = Arc::new(MockFs::default());
+        let mut cpu: Cpu = sampled("", fs.clone());
+
+        fs.set("/proc/stat", "cpu  0 0 0 0 0 0 0 0 0 0\n");
+        cpu.update();
+        // guest time is part of user already and counted once
+        fs.set("/proc/stat", "cpu  30 10 10 20 20 5 5 0 40 0\n");
+        cpu.update();
+        let placeholders = ["cpu", "cpu.user", "cpu.system", "cpu.iowait", "cpu.idle", "cpu.steal"];
+        let out: Vec<_> = placeholders.iter().map(|p| output(&cpu, p).unwrap()).collect();
+        assert_eq!(out, ["60", "40", "20", "20", "20", "00"]);
+
+        fs.set("/proc/stat", "cpu  30 10 10 20 20 5 5 100 40 0\n");
+        cpu.update();
+        assert_eq!(output(&cpu, "cpu.steal").as_deref(), Some("100"));
+        assert_eq!(output(&cpu, "cpu").as_deref(), Some("100"));
+    }
+
+    #[test]
+    fn huge_counters_and_deltas() {
+        let fs = Arc::new(MockFs::default());
+        let mut cpu: Cpu = sampled("", fs.clone());
+
+        fs.set("/proc/stat", "cpu  5000000000 0 0 15000000000 0 0 0 0 0 0\n");
+        cpu.update();
+        fs.set("/proc/stat", "cpu  8000000000 0 0 16000000000 0 0 0 0 0 0\n");
+        cpu.update();
+        assert_eq!(output(&cpu, "cpu").as_deref(), Some("75"));
+    }
+
+    #[test]
+    fn zero_delta_keeps_the_last_usage() {
+        let fs = Arc::new(MockFs::default());
+        let mut cpu: Cpu = sampled("", fs.clone());
+
+        fs.set("/proc/stat", "cpu  0 0 0 100\ncpu0 0 0 0 100\n");
+        cpu.update();
+        cpu.update();
+        assert_eq!(output(&cpu, "cpu"), None);
+
+        fs.set("/proc/stat", "cpu  50 0 0 150\ncpu0 50 0 0 150\n");
+        cpu.update();
+        cpu.update();
+        assert_eq!(output(&cpu, "cpu").as_deref(), Some("50"));
+        assert_eq!(output(&cpu, "cpu.0").as_deref(), Some("50"));
+        assert_eq!(cpu.history, [50.0]);
+    }
+
+    #[test]
+    fn counters_going_backwards_start_over() {
+        let fs = Arc::new(MockFs::default());
+        let mut cpu: Cpu = sampled("", fs.clone());
+
+        fs.set("/proc/stat", "cpu  0 0 0 100\ncpu0 500 0 0 500\n");
+        cpu.update();
+        fs.set("/proc/stat", "cpu  50 0 0 150\ncpu0 10 0 0 10\n");
+        cpu.update();
+        assert_eq!(output(&cpu, "cpu.0"), None);
+        fs.set("/proc/stat", "cpu  50 0 0 250\ncpu0 10 0 0 110\n");
+        cpu.update();
+        assert_eq!(output(&cpu, "cpu.0").as_deref(), Some("00"));
+    }
+
+    #[test]
+    fn per_core_and_busiest() {
+        let fs = Arc::new(MockFs::default());
+        let mut cpu: Cpu = sampled("", fs.clone());
+
+        fs.set("/proc/stat", "cpu  0 0 0 300\ncpu0 0 0 0 100\ncpu1 0 0 0 100\ncpu3 0 0 0 100\n");
+        cpu.update();
+        // one thread pegs core 1, core 2 is offline
+        fs.set("/proc/stat", "cpu  110 0 10 480\ncpu0 5 0 0 195\ncpu1 100 0 0 100\ncpu3 5 0 10 185\n");
+        cpu.update();
+
+        assert_eq!(output(&cpu, "cpu").as_deref(), Some("40"));
+        assert_eq!(output(&cpu, "cpu.0").as_deref(), Some("05"));
+        assert_eq!(output(&cpu, "cpu.1").as_deref(), Some("100"));
+        assert_eq!(output(&cpu, "cpu.2"), None);
+        assert_eq!(output(&cpu, "cpu.3").as_deref(), Some("15"));
+        assert_eq!(output(&cpu, "cpu.4"), None);
+        assert_eq!(output(&cpu, "cpu.max").as_deref(), Some("100"));
+        assert_eq!(output(&cpu, "cpu.cores").as_deref(), Some("▁█▂"));
+    }
+
+    #[test]
+    fn graph_keeps_the_last_samples() {
+        let fs = Arc::new(MockFs::default());
+        let mut cpu: Cpu = sampled("[cpu]\nhistory = 3\nglyphs = \"_-^\"\n", fs.clone());
+
+        let mut busy = 0;
+        for (i, percent) in [0, 0, 10, 50, 90].iter().enumerate() {
+            busy += percent;
+            fs.set("/proc/stat", &format!("cpu  {} 0 0 {}\n", busy, 100 * (i + 1) - busy));
+            cpu.update();
+        }
+        assert_eq!(output(&cpu, "cpu.graph").as_deref(), Some("_-^"));
+    }
+
+    #[test]
+    fn glyphs_span_the_range() {
+        let cpu: Cpu = sampled("", Arc::new(MockFs::default()));
+        let glyphs: String = [0.0, 7.0, 8.0, 50.0, 92.0, 93.0, 100.0, 130.0].iter().map(|&u| cpu.glyph(u)).collect();
+        assert_eq!(glyphs, "▁▁▂▅▇███");
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        let cpu: Cpu = sampled("", Arc::new(MockFs::default()));
+        assert!(cpu.check_args("cpu.12", &Args::default()).is_ok());
+        assert!(cpu.check_args("cpu.graph", &Args::default()).is_ok());
+        assert!(cpu.check_args("cpu.iowait", &Args::default()).is_ok());
+        assert!(cpu.check_args("cpu.avg", &Args::default()).is_err());
+    }
+
+    #[test]
+    fn config_is_checked() {
+        let error = |text: &str| Cpu::init(&Config::parse(text).unwrap()).unwrap_err().to_string();
+        assert_eq!(error("[cpu]\nhistory = 0\n"), "`config.toml` line 2: `cpu.history` must be at least 1");
+        assert_eq!(error("[cpu]\nglyphs = \"\"\n"), "`config.toml` line 2: `cpu.glyphs` must not be empty");
+    }
+}
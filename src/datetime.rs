@@ -1,22 +1,341 @@
 extern crate chrono;
+extern crate chrono_tz;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::format::{Fixed, Item, Numeric, StrftimeItems};
+use chrono::{DateTime, Local, Offset, TimeZone, Utc};
+use chrono_tz::{Tz, TZ_VARIANTS};
+
+use crate::schema::suggest;
+use crate::{Args, Block, Config};
+
+// [datetime]
+// format = "%A %b %Y-%m-%d %H:%M"
+// timezone = "Europe/Stockholm"
+//
+// [datetime.clocks.tokyo]       # {clock.tokyo}
+// timezone = "Asia/Tokyo"
+// format = "%H:%M"
+
+const DEFAULT_FORMAT: &str = "%A %b %Y-%m-%d %H:%M";
+const DEFAULT_ALT_FORMAT: &str = "%H:%M:%S";
+
+/// Keys of a `[datetime.clocks.NAME]` table.
+const CLOCK_KEYS: &[&str] = &["timezone", "format", "alt_format"];
+
+/// A time zone with its patterns.
+#[derive(Debug, PartialEq, Clone)]
+struct Clock {
+    /// `None` for the local time zone.
+    timezone: Option<Tz>,
+    format: String,
+    alt_format: String,
+}
+
+impl Clock {
+    fn render(&self, now: &DateTime<Utc>, fmt: &str) -> String {
+        match self.timezone {
+            Some(tz) => now.with_timezone(&tz).format(fmt).to_string(),
+            None => now.with_timezone(&Local).format(fmt).to_string(),
+        }
+    }
+
+    /// How long from `now` until `fmt` renders differently, at the next
+    /// boundary of the smallest unit in it.
+    fn until_change(&self, now: &DateTime<Utc>, fmt: &str) -> Duration {
+        // boundaries are in local time, which matters for offsets like +05:30
+        let offset = match self.timezone {
+            Some(tz) => tz.offset_from_utc_datetime(&now.naive_utc()).fix().local_minus_utc(),
+            None => Local.offset_from_utc_datetime(&now.naive_utc()).local_minus_utc(),
+        };
+        let unit = resolution(fmt) * 1000;
+        let local = now.timestamp_millis() + i64::from(offset) * 1000;
+        Duration::from_millis((unit - local.rem_euclid(unit)) as u64)
+    }
+}
+
+/// Seconds between changes of `fmt`, from a second for `%S` up to a day for
+/// a date.
+fn resolution(fmt: &str) -> i64 {
+    StrftimeItems::new(fmt)
+        .map(|item| match item {
+            Item::Numeric(n, _) => match n {
+                Numeric::Minute => 60,
+                Numeric::Hour | Numeric::Hour12 => 3600,
+                Numeric::Second | Numeric::Nanosecond | Numeric::Timestamp => 1,
+                _ => 86400,
+            },
+            Item::Fixed(f) => match f {
+                Fixed::ShortMonthName | Fixed::LongMonthName | Fixed::ShortWeekdayName | Fixed::LongWeekdayName => 86400,
+                // daylight saving time starts and ends on the hour
+                Fixed::LowerAmPm
+                | Fixed::UpperAmPm
+                | Fixed::TimezoneName
+                | Fixed::TimezoneOffset
+                | Fixed::TimezoneOffsetColon
+                | Fixed::TimezoneOffsetColonZ
+                | Fixed::TimezoneOffsetZ => 3600,
+                // fractions of a second and RFC 2822/3339 dates, which have
+                // seconds; updating more often than every second isn't worth it
+                _ => 1,
+            },
+            _ => 86400,
+        })
+        .min()
+        .unwrap_or(86400)
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Time {
-    val: String,
+    val: DateTime<Utc>,
+    /// The clock of `{datetime}`, also the defaults of the named clocks.
+    local: Clock,
+    /// The `{clock.NAME}` clocks by name.
+    clocks: HashMap<String, Clock>,
+    show_alt: bool,
+}
+
+impl Block for Time {
+    fn init(config: &Config) -> crate::Result<Time> {
+        let table = config.block("datetime");
+        let local = read_clock(config, table, &["datetime"], None)?;
+
+        let mut clocks = HashMap::new();
+        match table.and_then(|t| t.get("clocks")) {
+            Some(toml::Value::Table(t)) => {
+                for (name, clock) in t {
+                    let key = ["datetime", "clocks", name.as_str()];
+                    let clock = match clock {
+                        toml::Value::Table(c) => c,
+                        _ => return Err(config.error_at(&key, format!("`{}` must be a table", key.join(".")))),
+                    };
+                    for k in clock.keys() {
+                        if !CLOCK_KEYS.contains(&k.as_str()) {
+                            let msg = format!("unknown key `{}.{}`{}", key.join("."), k, suggest(k, CLOCK_KEYS.iter()));
+                            return Err(config.error_at(&[&key[..], &[k.as_str()]].concat(), msg));
+                        }
+                    }
+                    clocks.insert(name.to_string(), read_clock(config, Some(clock), &key, Some(&local))?);
+                }
+            }
+            Some(_) => return Err(config.error_at(&["datetime", "clocks"], "`datetime.clocks` must be a table")),
+            None => {}
+        }
+
+        Ok(Time {
+            val: Utc::now(),
+            local,
+            clocks,
+            show_alt: false,
+        })
+    }
+
+    fn update(&mut self) {
+        self.val = Utc::now();
+    }
+
+    // {datetime:%H:%M} and {clock.NAME:%H:%M} take a strftime pattern
+    fn output(&self, placeholder: &str, args: &Args) -> Option<String> {
+        let clock = self.clock(placeholder)?;
+        Some(clock.render(&self.val, self.pattern(clock, args)))
+    }
+
+    // redraws on the minute for `%H:%M` and every second for `%S`
+    fn until_change(&self, placeholders: &[(&str, &Args)]) -> Option<Duration> {
+        let now = Utc::now();
+        placeholders
+            .iter()
+            .filter_map(|(placeholder, args)| {
+                let clock = self.clock(placeholder)?;
+                Some(clock.until_change(&now, self.pattern(clock, args)))
+            })
+            .min()
+    }
+
+    fn action(&mut self, _placeholder: &str, action: &str) -> crate::Result<()> {
+        match action {
+            "toggle_format" => self.show_alt = !self.show_alt,
+            _ => return Err(format!("unknown action `{}`, expected `toggle_format`", action).into()),
+        }
+        Ok(())
+    }
+
+    fn check_args(&self, placeholder: &str, args: &Args) -> crate::Result<()> {
+        if self.clock(placeholder).is_none() {
+            let name = placeholder.strip_prefix("clock.").unwrap_or("");
+            if name.is_empty() {
+                return Err("expected `{clock.NAME}` with NAME from `datetime.clocks`".into());
+            }
+            let names: Vec<_> = self.clocks.keys().map(|k| k.as_str()).collect();
+            return Err(format!("no clock `{}` in `datetime.clocks`{}", name, suggest(name, names.iter())).into());
+        }
+        if let Some(fmt) = args.positional() {
+            check_format(fmt)?;
+        }
+        Ok(())
+    }
+
+    fn placeholders() -> &'static [&'static str] {
+        &["datetime", "clock.NAME"]
+    }
+
+    fn config_keys() -> &'static [&'static str] {
+        &["format", "alt_format", "timezone", "clocks"]
+    }
 }
 
 impl Time {
-    pub fn init() -> Time {
-        Time { val: String::new() }
+    fn clock(&self, placeholder: &str) -> Option<&Clock> {
+        match placeholder {
+            "datetime" => Some(&self.local),
+            _ => self.clocks.get(placeholder.strip_prefix("clock.")?),
+        }
+    }
+
+    /// The strftime pattern `clock` is shown with.
+    fn pattern<'a>(&self, clock: &'a Clock, args: &'a Args) -> &'a str {
+        if self.show_alt {
+            &clock.alt_format
+        } else {
+            args.positional().unwrap_or(&clock.format)
+        }
+    }
+}
+
+/// Reads a clock from `table`, found at `key` in the config, falling back to
+/// `defaults` for what it doesn't set.
+fn read_clock(
+    config: &Config,
+    table: Option<&toml::value::Table>,
+    key: &[&str],
+    defaults: Option<&Clock>,
+) -> crate::Result<Clock> {
+    let string = |k: &str| -> crate::Result<Option<String>> {
+        match table.and_then(|t| t.get(k)) {
+            Some(toml::Value::String(s)) => Ok(Some(s.to_string())),
+            Some(_) => {
+                let msg = format!("`{}.{}` must be a string", key.join("."), k);
+                Err(config.error_at(&[key, &[k]].concat(), msg))
+            }
+            None => Ok(None),
+        }
+    };
+    let error = |k: &str, e: String| config.error_at(&[key, &[k]].concat(), format!("`{}.{}`: {}", key.join("."), k, e));
+
+    let timezone = match string("timezone")? {
+        Some(name) => Some(parse_timezone(&name).map_err(|e| error("timezone", e))?),
+        None => defaults.and_then(|d| d.timezone),
+    };
+
+    let format = string("format")?
+        .or_else(|| defaults.map(|d| d.format.clone()))
+        .unwrap_or_else(|| DEFAULT_FORMAT.to_string());
+    check_format(&format).map_err(|e| error("format", e))?;
+
+    let alt_format = string("alt_format")?
+        .or_else(|| defaults.map(|d| d.alt_format.clone()))
+        .unwrap_or_else(|| DEFAULT_ALT_FORMAT.to_string());
+    check_format(&alt_format).map_err(|e| error("alt_format", e))?;
+
+    Ok(Clock {
+        timezone,
+        format,
+        alt_format,
+    })
+}
+
+/// An IANA time zone like `Asia/Tokyo`.
+fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse().map_err(|_| {
+        let names: Vec<_> = TZ_VARIANTS.iter().map(|tz| tz.name()).collect();
+        format!("unknown time zone `{}`{}", name, suggest(name, names.iter()))
+    })
+}
+
+/// chrono fails to render a pattern with an unknown `%` specifier, so they
+/// are rejected upfront.
+fn check_format(fmt: &str) -> Result<(), String> {
+    if StrftimeItems::new(fmt).any(|i| i == Item::Error) {
+        Err(format!("invalid strftime pattern `{}`", fmt))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+    }
+
+    fn clock(timezone: &str, format: &str) -> Clock {
+        Clock {
+            timezone: Some(timezone.parse().unwrap()),
+            format: format.to_string(),
+            alt_format: DEFAULT_ALT_FORMAT.to_string(),
+        }
     }
 
-    pub fn update(&mut self) {
-        self.val = chrono::Local::now()
-            .format("%A %b %Y-%m-%d %H:%M")
-            .to_string();
+    #[test]
+    fn resolution_is_the_smallest_unit() {
+        assert_eq!(resolution("%H:%M:%S"), 1);
+        assert_eq!(resolution("%H:%M"), 60);
+        assert_eq!(resolution("%I %p"), 3600);
+        assert_eq!(resolution(DEFAULT_FORMAT), 60);
+        assert_eq!(resolution("%A %Y-%m-%d"), 86400);
+        assert_eq!(resolution("week %V"), 86400);
+        assert_eq!(resolution("%+"), 1);
+        assert_eq!(resolution("no time"), 86400);
+    }
+
+    #[test]
+    fn changes_at_local_boundaries() {
+        let now = at("2026-10-18T12:34:56.250Z");
+        let utc = clock("UTC", "%H:%M");
+        assert_eq!(utc.until_change(&now, "%H:%M"), Duration::from_millis(3750));
+        assert_eq!(utc.until_change(&now, "%S"), Duration::from_millis(750));
+        assert_eq!(utc.until_change(&now, "%H"), Duration::from_millis(25 * 60_000 + 3750));
+
+        // 18:04:56 in India, which is 5:30 ahead
+        let kolkata = clock("Asia/Kolkata", "%H");
+        assert_eq!(kolkata.until_change(&now, "%H"), Duration::from_millis(55 * 60_000 + 3750));
+        assert_eq!(kolkata.until_change(&now, "%F"), Duration::from_millis(5 * 3_600_000 + 55 * 60_000 + 3750));
+    }
+
+    #[test]
+    fn renders_named_clocks() {
+        let config = Config::parse(
+            "[datetime]\nformat = \"%H:%M\"\ntimezone = \"UTC\"\n\n[datetime.clocks.tokyo]\ntimezone = \"Asia/Tokyo\"\n",
+        ).unwrap();
+        let mut time = Time::init(&config).unwrap();
+        time.val = at("2026-10-18T23:30:00Z");
+
+        assert_eq!(time.output("datetime", &Args::default()).as_deref(), Some("23:30"));
+        assert_eq!(time.output("clock.tokyo", &Args::default()).as_deref(), Some("08:30"));
+        assert_eq!(time.output("clock.oslo", &Args::default()), None);
+
+        time.action("datetime", "toggle_format").unwrap();
+        assert_eq!(time.output("clock.tokyo", &Args::default()).as_deref(), Some("08:30:00"));
     }
 
-    pub fn output(&self) -> String {
-        self.val.to_string()
+    #[test]
+    fn config_errors_point_at_the_key() {
+        let error = |text: &str| Time::init(&Config::parse(text).unwrap()).unwrap_err().to_string();
+        assert_eq!(
+            error("[datetime]\ntimezone = \"Europe/Stokholm\"\n"),
+            "`config.toml` line 2: `datetime.timezone`: unknown time zone `Europe/Stokholm`, did you mean `Europe/Stockholm`?"
+        );
+        assert_eq!(
+            error("[datetime.clocks.ny]\nformat = \"%H:%Q\"\n"),
+            "`config.toml` line 2: `datetime.clocks.ny.format`: invalid strftime pattern `%H:%Q`"
+        );
+        assert_eq!(
+            error("[datetime.clocks.ny]\ntimzone = \"America/New_York\"\n"),
+            "`config.toml` line 2: unknown key `datetime.clocks.ny.timzone`, did you mean `timezone`?"
+        );
     }
 }
@@ -0,0 +1,460 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+pub(crate) use formaterror::ErrorKind;
+pub use formaterror::FormatError;
+
+// {name}              placeholder
+// {name:a=1,b=2}      placeholder with options
+// {datetime:%H:%M}    placeholder with a positional argument
+// {{ and }}           literal braces
+// [ BAT {bat}%]       group, hidden with its text if any placeholder in it has no value
+// \ escapes the next character, e.g. `\[` or `\,` and `\}` inside the arguments
+
+/// A parsed `format` string.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Format {
+    source: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Token {
+    Text(String),
+    Placeholder(Placeholder),
+    Group(Vec<Token>),
+}
+
+/// A `{name:args}` in the format string.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Placeholder {
+    name: String,
+    args: Args,
+    column: usize,
+    source: String,
+}
+
+/// The arguments after the `:` of a placeholder.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Result<Format, FormatError> {
+        let err = |column, kind| FormatError::new(s, column, kind);
+
+        // innermost group last, with the column of its `[`
+        let mut groups: Vec<(usize, Vec<Token>)> = vec![(0, Vec::new())];
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        let mut column = 0;
+
+        while let Some(c) = chars.next() {
+            column += 1;
+            match c {
+                '\\' => {
+                    let escaped = chars.next().ok_or_else(|| err(column, ErrorKind::TrailingEscape))?;
+                    column += 1;
+                    text.push(escaped);
+                }
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    column += 1;
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    column += 1;
+                    text.push('}');
+                }
+                '}' => return Err(err(column, ErrorKind::UnmatchedClose)),
+                '{' => {
+                    let tokens = &mut groups.last_mut().unwrap().1;
+                    push_text(tokens, &mut text);
+                    let p = parse_placeholder(&mut chars, &mut column)
+                        .map_err(|(col, kind)| err(col, kind))?;
+                    tokens.push(Token::Placeholder(p));
+                }
+                '[' => {
+                    push_text(&mut groups.last_mut().unwrap().1, &mut text);
+                    groups.push((column, Vec::new()));
+                }
+                ']' if groups.len() > 1 => {
+                    let (_, mut group) = groups.pop().unwrap();
+                    push_text(&mut group, &mut text);
+                    groups.last_mut().unwrap().1.push(Token::Group(group));
+                }
+                ']' => return Err(err(column, ErrorKind::UnmatchedGroupClose)),
+                c => text.push(c),
+            }
+        }
+
+        if groups.len() > 1 {
+            let (open, _) = groups.pop().unwrap();
+            return Err(err(open, ErrorKind::UnclosedGroup));
+        }
+
+        let mut tokens = groups.pop().unwrap().1;
+        push_text(&mut tokens, &mut text);
+
+        Ok(Format {
+            source: s.to_string(),
+            tokens,
+        })
+    }
+
+    /// The format string as written in the config.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Every placeholder, in the order they appear.
+    pub fn placeholders(&self) -> Vec<&Placeholder> {
+        fn collect<'a>(tokens: &'a [Token], out: &mut Vec<&'a Placeholder>) {
+            for t in tokens {
+                match t {
+                    Token::Placeholder(p) => out.push(p),
+                    Token::Group(g) => collect(g, out),
+                    Token::Text(_) => {}
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+        collect(&self.tokens, &mut out);
+        out
+    }
+
+    pub(crate) fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+}
+
+fn push_text(tokens: &mut Vec<Token>, text: &mut String) {
+    if !text.is_empty() {
+        tokens.push(Token::Text(std::mem::take(text)));
+    }
+}
+
+fn parse_placeholder(
+    chars: &mut Peekable<Chars>,
+    column: &mut usize,
+) -> Result<Placeholder, (usize, ErrorKind)> {
+    let open = *column;
+    let mut name = String::new();
+    let mut source = String::new();
+
+    loop {
+        let c = chars.next().ok_or((open, ErrorKind::Unclosed))?;
+        *column += 1;
+        match c {
+            '}' | ':' if name.is_empty() => return Err((*column, ErrorKind::EmptyName)),
+            '}' => {
+                return Ok(Placeholder {
+                    name,
+                    args: Args::default(),
+                    column: open,
+                    source,
+                })
+            }
+            ':' => break,
+            c if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' => {
+                name.push(c);
+                source.push(c);
+            }
+            c => return Err((*column, ErrorKind::InvalidChar(c))),
+        }
+    }
+
+    source.push(':');
+    let mut args = Args::default();
+    let mut item = String::new();
+    let mut key = None;
+
+    loop {
+        let c = chars.next().ok_or((open, ErrorKind::Unclosed))?;
+        *column += 1;
+        match c {
+            '\\' => {
+                let escaped = chars.next().ok_or((open, ErrorKind::Unclosed))?;
+                *column += 1;
+                source.push(c);
+                source.push(escaped);
+                item.push(escaped);
+                continue;
+            }
+            '{' => return Err((*column, ErrorKind::NestedOpen)),
+            '}' | ',' => {
+                match key.take() {
+                    Some(k) => args.options.push((k, std::mem::take(&mut item))),
+                    None if !item.is_empty() => args.positional.push(std::mem::take(&mut item)),
+                    None => {}
+                }
+                if c == '}' {
+                    return Ok(Placeholder {
+                        name,
+                        args,
+                        column: open,
+                        source,
+                    });
+                }
+            }
+            '=' if key.is_none() => {
+                let k = item.trim().to_string();
+                if k.is_empty() {
+                    return Err((*column, ErrorKind::EmptyKey));
+                }
+                key = Some(k);
+                item.clear();
+            }
+            c => item.push(c),
+        }
+        source.push(c);
+    }
+}
+
+impl Placeholder {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn args(&self) -> &Args {
+        &self.args
+    }
+
+    /// 1-based column of the opening brace.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// The text between the braces, unique for each distinct placeholder.
+    pub(crate) fn key(&self) -> &str {
+        &self.source
+    }
+
+    /// Checks the options every placeholder understands.
+    pub(crate) fn check_args(&self) -> Result<(), String> {
+        for key in &["width", "min", "max"] {
+            if let Some(v) = self.args.get(key) {
+                if v.parse::<f64>().is_err() {
+                    return Err(format!("`{}` must be a number, found `{}`", key, v));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `out` lies within the `min` and `max` options, `{upload:min=0.1}`
+    /// hides the upload speed until it reaches 0.1.
+    ///
+    /// Outputs that don't start with a number are always shown.
+    pub(crate) fn in_range(&self, out: &str) -> bool {
+        let val = match leading_number(out) {
+            Some(v) => v,
+            None => return true,
+        };
+
+        let bound = |key| self.args.get(key).and_then(|v| v.parse::<f64>().ok());
+        bound("min").is_none_or(|min| val >= min) && bound("max").is_none_or(|max| val <= max)
+    }
+
+    /// Applies the options every placeholder understands, like `width`, to a block's output.
+    pub(crate) fn pad(&self, out: String) -> String {
+        let width = match self.args.get("width").and_then(|w| w.parse::<usize>().ok()) {
+            Some(w) => w,
+            None => return out,
+        };
+
+        match self.args.get("align") {
+            Some("left") => format!("{:<1$}", out, width),
+            Some("center") => format!("{:^1$}", out, width),
+            _ => format!("{:>1$}", out, width),
+        }
+    }
+}
+
+/// The number a block's output starts with, `42` for `42%`.
+pub(crate) fn leading_number(out: &str) -> Option<f64> {
+    let out = out.trim();
+    let end = out
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(out.len());
+    out[..end].parse().ok()
+}
+
+impl Args {
+    /// The value of option `key`, as in `{name:key=value}`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The first argument without a `key=`, as in `{datetime:%H:%M}`.
+    pub fn positional(&self) -> Option<&str> {
+        self.positional.first().map(|s| s.as_str())
+    }
+}
+
+mod formaterror {
+    use std::fmt;
+
+    #[derive(Debug, PartialEq, Clone)]
+    pub enum ErrorKind {
+        Unclosed,
+        UnmatchedClose,
+        NestedOpen,
+        EmptyName,
+        EmptyKey,
+        InvalidChar(char),
+        /// The name and a hint at the closest known one, if any.
+        UnknownPlaceholder(String, String),
+        InvalidArgs(String, String),
+        UnclosedGroup,
+        UnmatchedGroupClose,
+        TrailingEscape,
+    }
+
+    /// An error in the `format` string, pointing at the offending column.
+    #[derive(Debug, PartialEq, Clone)]
+    pub struct FormatError {
+        format: String,
+        column: usize,
+        kind: ErrorKind,
+    }
+
+    impl FormatError {
+        pub(crate) fn new(format: &str, column: usize, kind: ErrorKind) -> FormatError {
+            FormatError {
+                format: format.to_string(),
+                column,
+                kind,
+            }
+        }
+
+        /// 1-based column the error points at.
+        pub fn column(&self) -> usize {
+            self.column
+        }
+    }
+
+    impl std::error::Error for FormatError {}
+
+    impl fmt::Display for ErrorKind {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ErrorKind::Unclosed => f.write_str("unclosed `{`"),
+                ErrorKind::UnmatchedClose => f.write_str("unmatched `}`, write `}}` for a literal brace"),
+                ErrorKind::NestedOpen => f.write_str("`{` inside a placeholder, escape it with `\\{`"),
+                ErrorKind::EmptyName => f.write_str("placeholder without a name"),
+                ErrorKind::EmptyKey => f.write_str("option without a name before `=`"),
+                ErrorKind::InvalidChar(c) => write!(f, "invalid character `{}` in placeholder name", c),
+                ErrorKind::UnknownPlaceholder(s, hint) => write!(f, "unknown placeholder `{}`{}", s, hint),
+                ErrorKind::InvalidArgs(s, e) => write!(f, "`{}`: {}", s, e),
+                ErrorKind::UnclosedGroup => f.write_str("unclosed `[`"),
+                ErrorKind::UnmatchedGroupClose => f.write_str("unmatched `]`, write `\\]` for a literal bracket"),
+                ErrorKind::TrailingEscape => f.write_str("`\\` at the end of the format"),
+            }
+        }
+    }
+
+    impl fmt::Display for FormatError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            writeln!(f, "`format` column {}: {}", self.column, self.kind)?;
+            writeln!(f, "    {}", self.format)?;
+            write!(f, "    {:>1$}", "^", self.column)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fails_at(s: &str, column: usize, kind: ErrorKind) {
+        assert_eq!(Format::parse(s), Err(FormatError::new(s, column, kind)), "{}", s);
+    }
+
+    #[test]
+    fn literal_braces() {
+        let format = Format::parse("{{a}} {b}}}").unwrap();
+        assert_eq!(format.tokens()[0], Token::Text(String::from("{a} ")));
+        assert_eq!(format.placeholders()[0].name(), "b");
+        assert_eq!(format.tokens()[2], Token::Text(String::from("}")));
+    }
+
+    #[test]
+    fn positional_and_options() {
+        let format = Format::parse("{datetime:%H:%M} {net:unit=KiB,width=6,unit=MiB}").unwrap();
+        let p = format.placeholders();
+
+        assert_eq!(p[0].args().positional(), Some("%H:%M"));
+        assert_eq!(p[0].key(), "datetime:%H:%M");
+        assert_eq!(p[0].column(), 1);
+
+        assert_eq!(p[1].args().positional(), None);
+        assert_eq!(p[1].args().get("width"), Some("6"));
+        // the last one wins
+        assert_eq!(p[1].args().get("unit"), Some("MiB"));
+        assert_eq!(p[1].column(), 18);
+    }
+
+    #[test]
+    fn escapes_in_args() {
+        let format = Format::parse(r"\[{a:x\,y,k=v\}w,eq=1=2}").unwrap();
+        assert_eq!(format.tokens()[0], Token::Text(String::from("[")));
+
+        let p = format.placeholders()[0];
+        assert_eq!(p.args().positional(), Some("x,y"));
+        assert_eq!(p.args().get("k"), Some("v}w"));
+        assert_eq!(p.args().get("eq"), Some("1=2"));
+        // escapes are kept in the key so `{a:\,}` and `{a:,}` differ
+        assert_eq!(p.key(), r"a:x\,y,k=v\}w,eq=1=2");
+    }
+
+    #[test]
+    fn error_columns() {
+        fails_at("ab {cpu", 4, ErrorKind::Unclosed);
+        fails_at("{cpu:x", 1, ErrorKind::Unclosed);
+        fails_at("a}b", 2, ErrorKind::UnmatchedClose);
+        fails_at("{a:{}", 4, ErrorKind::NestedOpen);
+        fails_at("x {}", 4, ErrorKind::EmptyName);
+        fails_at("{:x}", 2, ErrorKind::EmptyName);
+        fails_at("{a:=1}", 4, ErrorKind::EmptyKey);
+        fails_at("{a b}", 3, ErrorKind::InvalidChar(' '));
+        fails_at("a [b", 3, ErrorKind::UnclosedGroup);
+        fails_at("[a [b]", 1, ErrorKind::UnclosedGroup);
+        fails_at("a]", 2, ErrorKind::UnmatchedGroupClose);
+        fails_at("ab\\", 3, ErrorKind::TrailingEscape);
+    }
+
+    #[test]
+    fn unbalanced_nested_groups() {
+        assert!(Format::parse("[a[b]]").is_ok());
+        fails_at("[a[b]", 1, ErrorKind::UnclosedGroup);
+        fails_at("[a[b]]]", 7, ErrorKind::UnmatchedGroupClose);
+    }
+
+    #[test]
+    fn caret_counts_characters() {
+        let e = Format::parse("°C {temp").unwrap_err();
+        assert_eq!(e.column(), 4);
+        assert_eq!(e.to_string(), "`format` column 4: unclosed `{`\n    °C {temp\n       ^");
+    }
+
+    #[test]
+    fn unknown_placeholders_and_args_point_at_the_brace() {
+        let check = |s: &str| {
+            let config = crate::Config::parse(&format!("format = \"{}\"", s)).unwrap();
+            crate::Registry::default().check(&config, &Format::parse(s).unwrap()).unwrap_err().to_string()
+        };
+        let e = check("° {cpuu}");
+        assert!(e.contains("column 3: unknown placeholder `cpuu`, did you mean `cpu`?\n    ° {cpuu}\n      ^"), "{}", e);
+        let e = check("°{cpu} {memory:width=x}");
+        assert!(e.contains("column 8: `memory`: `width` must be a number, found `x`"), "{}", e);
+    }
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::block::check_placeholder;
+use crate::sys::Fs;
+use crate::{Args, Block, Config};
+
+// {freq}      average clock of the cores, in GHz
+// {freq.max}  clock of the fastest core
+// {freq.min}  clock of the slowest core, low while throttling
+//
+// {freq:unit=MHz} shows MHz instead
+
+const CPUS: &str = "/sys/devices/system/cpu";
+
+const PLACEHOLDERS: &[&str] = &["freq", "freq.max", "freq.min"];
+
+#[derive(Debug)]
+pub struct Freq {
+    /// kHz, by core
+    cores: Vec<f64>,
+    fs: Arc<dyn Fs>,
+}
+
+impl Block for Freq {
+    fn init(config: &Config) -> crate::Result<Freq> {
+        Ok(Freq {
+            cores: Vec::new(),
+            fs: config.fs(),
+        })
+    }
+
+    fn update(&mut self) {
+        self.cores = read_freqs(&*self.fs);
+        if self.cores.is_empty() {
+            eprintln!("Error: no `cpufreq/scaling_cur_freq` in `{}`", CPUS);
+        }
+    }
+
+    fn output(&self, placeholder: &str, args: &Args) -> Option<String> {
+        if self.cores.is_empty() {
+            return None;
+        }
+        let khz = match placeholder {
+            "freq.max" => self.cores.iter().copied().fold(f64::MIN, f64::max),
+            "freq.min" => self.cores.iter().copied().fold(f64::MAX, f64::min),
+            _ => self.cores.iter().sum::<f64>() / self.cores.len() as f64,
+        };
+        match args.get("unit").unwrap_or("GHz") {
+            "MHz" => Some(format!("{:.0}", khz / 1e3)),
+            _ => Some(format!("{:.1}", khz / 1e6)),
+        }
+    }
+
+    fn check_args(&self, placeholder: &str, args: &Args) -> crate::Result<()> {
+        check_placeholder(placeholder, PLACEHOLDERS)?;
+        match args.get("unit") {
+            None | Some("GHz") | Some("MHz") => Ok(()),
+            Some(u) => Err(format!("unknown unit `{}`, expected `GHz` or `MHz`", u).into()),
+        }
+    }
+
+    fn placeholders() -> &'static [&'static str] {
+        PLACEHOLDERS
+    }
+}
+
+/// The current clock of every online core in kHz, as the cpufreq driver
+/// last saw it.
+fn read_freqs(fs: &dyn Fs) -> Vec<f64> {
+    let mut cores: Vec<(u32, f64)> = fs
+        .list(Path::new(CPUS))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| {
+            let n = name.strip_prefix("cpu")?.parse().ok()?;
+            let path = Path::new(CPUS).join(&name).join("cpufreq/scaling_cur_freq");
+            // offline cores have no cpufreq
+            let khz = fs.read(&path).ok()?.trim().parse().ok()?;
+            Some((n, khz))
+        })
+        .collect();
+    cores.sort_by_key(|(n, _)| *n);
+    cores.into_iter().map(|(_, khz)| khz).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::{fixture, sampled};
+    use crate::Format;
+
+    fn args(args: &str) -> Args {
+        let format = Format::parse(&format!("{{freq:{}}}", args)).unwrap();
+        format.placeholders()[0].args().clone()
+    }
+
+    #[test]
+    fn reads_online_cores() {
+        assert_eq!(read_freqs(&*fixture("laptop")), [2400012.0, 3900000.0, 800000.0, 1198734.0]);
+        assert_eq!(read_freqs(&*fixture("server")), [2994000.0, 1500000.0]);
+        assert!(read_freqs(&*fixture("desktop")).is_empty());
+    }
+
+    #[test]
+    fn average_and_extremes() {
+        let f: Freq = sampled("", fixture("laptop"));
+        let out = |p: &str, a: &str| f.output(p, &args(a));
+        assert_eq!(out("freq", "unit=GHz").as_deref(), Some("2.1"));
+        assert_eq!(out("freq.max", "unit=GHz").as_deref(), Some("3.9"));
+        assert_eq!(out("freq.min", "unit=MHz").as_deref(), Some("800"));
+        assert_eq!(sampled::<Freq>("", fixture("desktop")).output("freq", &Args::default()), None);
+    }
+
+    #[test]
+    fn checks_placeholders_and_units() {
+        let f: Freq = sampled("", fixture("desktop"));
+        assert!(f.check_args("freq.min", &Args::default()).is_ok());
+        assert!(f.check_args("freq.avg", &Args::default()).is_err());
+        assert!(f.check_args("freq", &args("unit=kHz")).is_err());
+    }
+}
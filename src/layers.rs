@@ -0,0 +1,222 @@
+use std::ffi::CStr;
+use std::path::{Path, PathBuf};
+
+use crate::Result;
+
+// Config files are read from lowest to highest precedence, a later file
+// overriding single keys of the ones before it:
+//
+//   $XDG_CONFIG_DIRS/rustystatus/config.toml   (/etc/xdg by default)
+//   $XDG_CONFIG_DIRS/rustystatus/hosts/HOSTNAME.toml
+//   $XDG_CONFIG_HOME/rustystatus/config.toml   (~/.config by default)
+//   $XDG_CONFIG_HOME/rustystatus/hosts/HOSTNAME.toml
+//
+// A file can pull in others with `include = ["base.toml"]`, relative to its
+// own directory, which it then overrides.
+
+/// Every file the config is looked up in, from lowest to highest precedence,
+/// whether it exists or not.
+pub(crate) fn candidates() -> Vec<PathBuf> {
+    let mut dirs = system_dirs();
+    // the first of $XDG_CONFIG_DIRS is the most important one
+    dirs.reverse();
+    if let Some(d) = dirs::config_dir() {
+        dirs.push(d);
+    }
+
+    let host = hostname();
+    let mut files = Vec::new();
+    for dir in dirs {
+        let dir = dir.join("rustystatus");
+        files.push(dir.join("config.toml"));
+        if let Some(host) = &host {
+            files.push(dir.join("hosts").join(format!("{}.toml", host)));
+        }
+    }
+    files
+}
+
+/// Directories of `$XDG_CONFIG_DIRS`, `/etc/xdg` if it's unset.
+fn system_dirs() -> Vec<PathBuf> {
+    let dirs: Vec<_> = std::env::var_os("XDG_CONFIG_DIRS")
+        .map(|v| std::env::split_paths(&v).filter(|p| p.is_absolute()).collect())
+        .unwrap_or_default();
+    if dirs.is_empty() {
+        vec![PathBuf::from("/etc/xdg")]
+    } else {
+        dirs
+    }
+}
+
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return None;
+    }
+    let name = CStr::from_bytes_until_nul(&buf).ok()?.to_str().ok()?;
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// A config file as it was read.
+#[derive(Debug, Clone)]
+pub(crate) struct Source {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+/// The merged config tables.
+pub(crate) struct Layers {
+    pub table: toml::value::Table,
+    /// Every file that was read, includes too, in the order they were merged.
+    pub sources: Vec<Source>,
+}
+
+/// Reads the files of `paths` that exist and merges them in order.
+pub(crate) fn load_existing(paths: &[PathBuf]) -> Result<Layers> {
+    let mut layers = Layers {
+        table: toml::value::Table::new(),
+        sources: Vec::new(),
+    };
+    for path in paths.iter().filter(|p| p.exists()) {
+        load_file(path, &mut layers, &mut Vec::new())?;
+    }
+    Ok(layers)
+}
+
+/// Reads `path` and the files it includes.
+pub(crate) fn load(path: &Path) -> Result<Layers> {
+    let mut layers = Layers {
+        table: toml::value::Table::new(),
+        sources: Vec::new(),
+    };
+    load_file(path, &mut layers, &mut Vec::new())?;
+    Ok(layers)
+}
+
+/// Merges `path` into `layers`, after the files it includes.
+///
+/// `stack` holds the files currently being included, to catch cycles.
+fn load_file(path: &Path, layers: &mut Layers, stack: &mut Vec<PathBuf>) -> Result<()> {
+    let raw = std::fs::read_to_string(path).map_err(|e| format!("could not read `{}`: {}", path.display(), e))?;
+    // parsed as a whole config too, only this way type errors come with a line
+    toml::from_str::<crate::Config>(&raw).map_err(|e| format!("`{}`: {}", path.display(), e))?;
+    let mut table: toml::value::Table = toml::from_str(&raw)?;
+
+    let canonical = path.canonicalize()?;
+    if stack.contains(&canonical) {
+        return Err(format!("`{}` includes itself", path.display()).into());
+    }
+    stack.push(canonical);
+
+    let includes = match table.remove("include") {
+        Some(toml::Value::Array(a)) => a,
+        Some(toml::Value::String(s)) => vec![toml::Value::String(s)],
+        Some(_) => return Err(format!("`{}`: `include` must be an array of paths", path.display()).into()),
+        None => Vec::new(),
+    };
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for include in includes {
+        let include = include
+            .as_str()
+            .ok_or_else(|| format!("`{}`: `include` must be an array of paths", path.display()))?;
+        load_file(&dir.join(include), layers, stack)?;
+    }
+
+    merge(&mut layers.table, table);
+    layers.sources.push(Source {
+        path: path.to_path_buf(),
+        text: raw,
+    });
+    stack.pop();
+    Ok(())
+}
+
+/// Merges `over` into `base`, tables key by key and anything else, arrays
+/// too, replacing what was there.
+fn merge(base: &mut toml::value::Table, over: toml::value::Table) {
+    for (key, val) in over {
+        match (base.get_mut(&key), val) {
+            (Some(toml::Value::Table(b)), toml::Value::Table(o)) => merge(b, o),
+            (_, val) => {
+                base.insert(key, val);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for test `name`.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustystatus-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, text: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn later_files_override_single_keys() {
+        let dir = temp_dir("layers");
+        let base = write(
+            &dir,
+            "etc/rustystatus/config.toml",
+            "format = \"{cpu}\"\n[cpu]\ninterval = 1\ncolor = \"#111111\"\nthresholds = [{ above = 50, color = \"#ff0000\" }]\n",
+        );
+        write(&dir, "home/rustystatus/team.toml", "[cpu]\ninterval = 2\nicon = \"C\"\n");
+        let user = write(
+            &dir,
+            "home/rustystatus/config.toml",
+            "include = [\"team.toml\"]\n[cpu]\ninterval = 5\nthresholds = []\n",
+        );
+
+        let layers = load_existing(&[base.clone(), dir.join("missing.toml"), user.clone()]).unwrap();
+        let expected: toml::value::Table = toml::from_str(
+            "format = \"{cpu}\"\n[cpu]\ninterval = 5\ncolor = \"#111111\"\nicon = \"C\"\nthresholds = []\n",
+        )
+        .unwrap();
+        assert_eq!(layers.table, expected);
+
+        let sources: Vec<_> = layers.sources.iter().map(|s| s.path.strip_prefix(&dir).unwrap()).collect();
+        assert_eq!(
+            sources,
+            [
+                Path::new("etc/rustystatus/config.toml"),
+                Path::new("home/rustystatus/team.toml"),
+                Path::new("home/rustystatus/config.toml"),
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn include_cycles_fail() {
+        let dir = temp_dir("cycles");
+        let own = write(&dir, "own.toml", "include = \"own.toml\"\n");
+        let e = load(&own).err().unwrap().to_string();
+        assert!(e.ends_with("own.toml` includes itself"), "{}", e);
+
+        let a = write(&dir, "a.toml", "include = [\"b.toml\"]\n");
+        write(&dir, "b.toml", "include = [\"a.toml\"]\n");
+        assert!(load(&a).is_err());
+
+        let e = load(&write(&dir, "c.toml", "include = [\"gone.toml\"]\n")).err().unwrap().to_string();
+        assert!(e.starts_with("could not read"), "{}", e);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,219 +1,273 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use std::thread::sleep;
 
 #[macro_use]
 extern crate serde_derive;
 extern crate dirs;
+extern crate inotify;
 extern crate reqwest;
 extern crate serde_json;
 extern crate toml;
+extern crate x11rb;
 
+pub mod block;
+mod click;
 mod cpu;
 mod datetime;
+pub mod format;
+mod freq;
+mod layers;
+mod load;
 mod mem;
 mod net;
+mod notify;
+pub mod output;
+mod reload;
+mod schema;
+mod secret;
+mod statuscmd;
+mod style;
+pub mod sys;
+mod thermal;
+mod uptime;
+mod users;
 mod weather;
 mod bat;
+mod worker;
+pub mod xroot;
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+pub use block::{Block, Registry};
+pub use format::{Args, Format};
+pub use output::Output;
 
-#[derive(Debug, PartialEq, Clone)]
-struct Modules {
-    time: Option<datetime::Time>,
-    weather: Option<weather::Weather>,
-    net: Option<net::Net>,
-    cpu: Option<cpu::Cpu>,
-    mem: Option<mem::Mem>,
-    bat: Option<bat::Battery>,
-}
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-impl Modules {
-    fn init(config: Config, s: &str) -> Result<Modules> {
-        let time = if s.contains("{datetime}") {
-            Some(datetime::Time::init())
-        } else {
-            None
-        };
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    format: Option<String>,
+    weather_apikey: Option<String>,
+    weather_city: Option<String>,
+    net_interface: Option<String>,
+    update_interval: Option<f64>,
+    output: Option<String>,
+    statuscmd: Option<bool>,
+    statuscmd_fifo: Option<String>,
+    fs_root: Option<PathBuf>,
+    // the file given to `load`, `None` when looked up in the XDG directories
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    // files watched for changes
+    #[serde(skip)]
+    files: Vec<PathBuf>,
+    // the files that were read, to point errors at their line
+    #[serde(skip)]
+    sources: Vec<layers::Source>,
+    // replaces the filesystem below `fs_root` in tests
+    #[serde(skip)]
+    fs: Option<Arc<dyn sys::Fs>>,
+    #[serde(flatten)]
+    blocks: HashMap<String, toml::Value>,
+}
 
-        let weather = if s.contains("weather") {
-            let url = config.format_url()?;
-            Some(weather::Weather::init(url))
-        } else {
-            None
-        };
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            format: Some("{datetime}".to_string()),
+            weather_apikey: None,
+            weather_city: None,
+            net_interface: None,
+            update_interval: None,
+            output: None,
+            statuscmd: None,
+            statuscmd_fifo: None,
+            fs_root: None,
+            path: None,
+            // still watched, so fixing a broken config takes effect
+            files: layers::candidates(),
+            sources: Vec::new(),
+            fs: None,
+            blocks: HashMap::new(),
+        }
+    }
+}
 
-        let net = if s.contains("{download}")
-                  || s.contains("{upload}") {
-            let interface = config.get_net_interface()?;
-            Some(net::Net::init(interface))
-        } else {
-            None
-        };
+impl Config {
+    /// Reads the config files in the XDG config directories, layered over
+    /// each other.
+    pub fn new() -> Result<Config> {
+        let candidates = layers::candidates();
+        let layers = layers::load_existing(&candidates)?;
+        if layers.sources.is_empty() {
+            let paths: Vec<_> = candidates.iter().map(|p| format!("`{}`", p.display())).collect();
+            return Err(format!("no config found, looked for {}", paths.join(", ")).into());
+        }
 
-        let cpu = if s.contains("{cpu}") {
-            Some(cpu::Cpu::init())
-        } else {
-            None
-        };
+        let mut files = candidates;
+        for s in &layers.sources {
+            if !files.contains(&s.path) {
+                files.push(s.path.clone());
+            }
+        }
+        let mut config = Config::from_layers(layers)?;
+        config.files = files;
+        Ok(config)
+    }
 
-        let mem = if s.contains("{memory}") {
-            Some(mem::Mem::init())
-        } else {
-            None
-        };
+    /// Reads the config at `path` and the files it includes.
+    pub fn load(path: &Path) -> Result<Config> {
+        let layers = layers::load(path)?;
+        let files = layers.sources.iter().map(|s| s.path.clone()).collect();
+        let mut config = Config::from_layers(layers)?;
+        config.path = Some(path.to_path_buf());
+        config.files = files;
+        Ok(config)
+    }
 
-        let bat = if s.contains("{bat}") {
-            Some(bat::Battery::init())
-        } else {
-            None
-        };
+    fn from_layers(mut layers: layers::Layers) -> Result<Config> {
+        if let Err((key, e)) = secret::resolve(&mut layers.table) {
+            let key: Vec<_> = key.iter().map(|k| k.as_str()).collect();
+            let location = schema::locate(&layers.sources, &key);
+            return Err(Box::new(schema::ConfigError::new(location, format!("`{}`: {}", key.join("."), e))));
+        }
 
-        let m = Modules {
-            time,
-            weather,
-            net,
-            cpu,
-            mem,
-            bat,
-        };
+        let mut config: Config = toml::Value::Table(layers.table).try_into()?;
+        config.sources = layers.sources;
 
-        Ok(m)
+        if let Some(secs) = config.update_interval {
+            config.duration(&["update_interval"], secs)?;
+        }
+        Ok(config)
     }
 
-    fn update_time(&mut self) -> String {
-        match self.time {
-            Some(ref mut v) => {
-                v.update();
-                v.output()
-            },
-            None => String::from("N/A"),
+    /// Reads the config again from where it came from.
+    pub(crate) fn reload(&self) -> Result<Config> {
+        match &self.path {
+            Some(p) => Config::load(p),
+            None => Config::new(),
         }
     }
 
-    fn update_net(&mut self) {
-        if let Some(ref mut v) = self.net {
-            v.update();
-        }
+    /// The files the config was read from, and the ones it would be read
+    /// from if they existed.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
     }
 
-    fn net_dl(&mut self) -> String {
-        match self.net {
-            Some(ref mut v) => {
-                match v.dl_output() {
-                    Some(s) => s.to_string(),
-                    None => String::from("N/A"),
-                }
-            },
-            None => String::from("N/A"),
-        }
+    pub fn update_interval(&self) -> Duration {
+        self.update_interval
+            .and_then(duration)
+            .unwrap_or(Duration::from_secs(1))
     }
 
-    fn net_up(&mut self) -> String {
-        match self.net {
-            Some(ref mut v) => {
-                match v.up_output() {
-                    Some(s) => s.to_string(),
-                    None => String::from("N/A"),
-                }
-            },
-            None => String::from("N/A"),
-        }
+    /// Name of the output backend, `dwm` unless set.
+    pub fn output(&self) -> &str {
+        self.output.as_deref().unwrap_or("dwm")
     }
 
-    fn update_weather(&mut self) -> String {
-        match self.weather {
-            Some(ref mut v) => {
-                v.update();
-                match v.output() {
-                    Some(s) => s,
-                    None => String::from("N/A"),
-                }
-            },
-            None => String::from("N/A"),
+    /// Overrides the `output` set in the config.
+    pub fn set_output(&mut self, name: &str) {
+        self.output = Some(name.to_string());
+    }
+
+    /// Whether to mark blocks for dwm's statuscmd patch and listen for its clicks.
+    pub fn statuscmd(&self) -> bool {
+        self.statuscmd.unwrap_or(false)
+    }
+
+    /// Where the blocks read `/proc`, `/sys` and `/run` from, below `fs_root` if set.
+    pub fn fs(&self) -> Arc<dyn sys::Fs> {
+        match &self.fs {
+            Some(fs) => fs.clone(),
+            None => Arc::new(sys::Root::new(self.fs_root.as_deref().unwrap_or(Path::new("/")))),
         }
     }
 
-    fn update_cpu(&mut self) -> String {
-        match self.cpu {
-            Some(ref mut v) => {
-                v.update();
-                match v.output() {
-                    Some(s) => s,
-                    None => String::from("N/A"),
-                }
-            },
-            None => String::from("N/A"),
+    /// The `[name]` table of block `name`, `None` if the config has none.
+    pub fn block(&self, name: &str) -> Option<&toml::value::Table> {
+        self.blocks.get(name).and_then(|v| v.as_table())
+    }
+
+    /// A string set in the `[name]` table of block `name`.
+    pub fn block_str(&self, name: &str, key: &str) -> Result<Option<&str>> {
+        match self.block(name).and_then(|t| t.get(key)) {
+            Some(toml::Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(self.error_at(&[name, key], format!("`{}.{}` must be a string", name, key))),
+            None => Ok(None),
         }
     }
 
-    fn update_mem(&mut self) -> String {
-        match self.mem {
-            Some(ref mut v) => {
-                v.update();
-                match v.output() {
-                    Some(s) => s,
-                    None => String::from("N/A"),
-                }
-            },
-            None => String::from("N/A"),
+    /// An integer set in the `[name]` table of block `name`.
+    pub fn block_int(&self, name: &str, key: &str) -> Result<Option<i64>> {
+        match self.block(name).and_then(|t| t.get(key)) {
+            Some(toml::Value::Integer(i)) => Ok(Some(*i)),
+            Some(_) => Err(self.error_at(&[name, key], format!("`{}.{}` must be an integer", name, key))),
+            None => Ok(None),
         }
     }
 
-    fn update_bat(&mut self) -> String {
-        match self.bat {
-            Some(ref mut v) => {
-                v.update();
-                match v.output() {
-                    Some(s) => s,
-                    None => String::from("N/A"),
-                }
-            },
-            None => String::from("N/A"),
+    /// A boolean set in the `[name]` table of block `name`.
+    pub fn block_bool(&self, name: &str, key: &str) -> Result<Option<bool>> {
+        match self.block(name).and_then(|t| t.get(key)) {
+            Some(toml::Value::Boolean(b)) => Ok(Some(*b)),
+            Some(_) => Err(self.error_at(&[name, key], format!("`{}.{}` must be true or false", name, key))),
+            None => Ok(None),
         }
     }
-}
 
-#[derive(Deserialize, Debug)]
-pub struct Config {
-    format: Option<String>,
-    weather_apikey: Option<String>,
-    weather_city: Option<String>,
-    net_interface: Option<String>,
-    update_interval: Option<f32>,
-}
+    /// A duration in seconds, like `interval`, set in the `[name]` table of block `name`.
+    pub(crate) fn block_secs(&self, name: &str, key: &str) -> Result<Option<Duration>> {
+        let val = match self.block(name).and_then(|t| t.get(key)) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
 
-impl Config {
-    pub fn new() -> Result<Config> {
-        let path = get_config_path()?;
-        let config_raw = std::fs::read_to_string(path)?;
-        let config = toml::from_str(config_raw.as_str())?;
-        Ok(config)
+        let secs = match val {
+            toml::Value::Integer(i) => *i as f64,
+            toml::Value::Float(f) => *f,
+            _ => {
+                let msg = format!("`{}.{}` must be a number of seconds", name, key);
+                return Err(self.error_at(&[name, key], msg));
+            }
+        };
+
+        self.duration(&[name, key], secs).map(Some)
     }
 
-    pub fn default() -> Config {
-        Config {
-            format: Some("{datetime}".to_string()),
-            weather_apikey: None,
-            weather_city: None,
-            net_interface: None,
-            update_interval: None,
-        }
+    /// `secs` of the config value at `key` as a duration, or an error pointing
+    /// at its line if it is too short.
+    fn duration(&self, key: &[&str], secs: f64) -> Result<Duration> {
+        duration(secs).ok_or_else(|| {
+            let msg = format!("`{}` must be at least {} seconds", key.join("."), MIN_DURATION.as_secs_f64());
+            self.error_at(key, msg)
+        })
     }
 
-    pub fn update_interval(&self) -> Duration {
-        Duration::from_millis((self.update_interval.unwrap_or(1.0) * 1000.0) as u64)
+    /// An error about `key`, a path of table names and the key, pointing at
+    /// the line that sets it.
+    pub(crate) fn error_at(&self, key: &[&str], msg: impl Into<String>) -> Box<dyn std::error::Error> {
+        Box::new(schema::ConfigError::new(schema::locate(&self.sources, key), msg.into()))
+    }
+
+    fn parse_format(&self) -> Result<Format> {
+        match &self.format {
+            Some(v) => Format::parse(v).map_err(|e| self.error_at(&["format"], e.to_string())),
+            None => Err("`format` not found in config.toml".into()),
+        }
     }
 
-    fn format_url(&self) -> std::result::Result<String, &'static str> {
-        let apikey = match &self.weather_apikey {
+    pub(crate) fn format_url(&self) -> Result<String> {
+        // the top level keys are still read for older configs
+        let apikey = match self.block_str("weather", "apikey")?.or(self.weather_apikey.as_deref()) {
             Some(s) => s,
-            None => return Err("`weather` module requires `weather_api` to be set in config.toml"),
+            None => return Err("`weather` block requires `weather.apikey` to be set".into()),
         };
 
-        let city = match &self.weather_city {
+        let city = match self.block_str("weather", "city")?.or(self.weather_city.as_deref()) {
             Some(s) => s,
-            None => return Err("`weather` module requires `weather_city` to be set in config.toml"),
+            None => return Err("`weather` block requires `weather.city` to be set".into()),
         };
 
         Ok(format!(
@@ -222,79 +276,211 @@ impl Config {
         ))
     }
 
-    fn get_net_interface(&self) -> Result<String> {
-        match &self.net_interface {
+    pub(crate) fn get_net_interface(&self) -> Result<String> {
+        match self.block_str("net", "interface")?.or(self.net_interface.as_deref()) {
             Some(e) => Ok(e.to_string()),
-            None => Err("`net` module requires `net_interface` to be set in config.toml".into()),
+            None => Err("`net` block requires `net.interface` to be set".into()),
         }
     }
 }
 
+/// Shortest interval or timeout, anything below keeps a worker spinning.
+const MIN_DURATION: Duration = Duration::from_millis(100);
+
+/// `secs` as a duration, `None` if it is below `MIN_DURATION` or not a number.
+fn duration(secs: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(secs).ok().filter(|d| *d >= MIN_DURATION)
+}
+
+#[cfg(test)]
+impl Config {
+    /// A config read from `text`, as if it was `config.toml`.
+    pub(crate) fn parse(text: &str) -> Result<Config> {
+        let source = layers::Source {
+            path: PathBuf::from("config.toml"),
+            text: text.to_string(),
+        };
+        let table = toml::from_str(text)?;
+        Config::from_layers(layers::Layers { table, sources: vec![source] })
+    }
+
+    /// Reads `/proc` and `/sys` from `fs` instead.
+    pub(crate) fn with_fs(mut self, fs: Arc<dyn sys::Fs>) -> Config {
+        self.fs = Some(fs);
+        self
+    }
+}
+
 pub fn run(config: Config) -> Result<()> {
-    let format = match &config.format {
-        Some(v) => v.to_string(),
-        None => return Err("`format` not found in config.toml".into()),
-    };
-    let update_interval = config.update_interval();
-    let mut modules = Modules::init(config, &format)?;
+    run_with(config, &Registry::default())
+}
 
-    loop {
-        let output = update(format.clone(), &mut modules);
-        call(&output)?;
-        sleep(update_interval);
+/// Like `run`, but takes the placeholders from `registry` instead of the builtin blocks.
+pub fn run_with(config: Config, registry: &Registry) -> Result<()> {
+    let mut output = output::from_name(config.output())?;
+
+    let res = run_output(config, registry, output.as_mut());
+    if res.is_err() {
+        // don't leave a stale status behind
+        if let Err(e) = output.clear() {
+            eprintln!("Error: {}", e);
+        }
     }
+    res
 }
 
-fn update(mut s: String, m: &mut Modules) -> String {
-    if s.contains("{datetime}") {
-        let t = m.update_time();
-        s = s.replace("{datetime}", &t);
-    };
+/// Updates every block of `config` once and writes a single status line.
+pub fn run_once(config: &Config, registry: &Registry) -> Result<()> {
+    let mut output = output::from_name(config.output())?;
+    let format = config.parse_format()?;
+    let blocks = registry.init_once(config, &format)?;
+    output.write(&blocks.render(&format))
+}
 
-    if s.contains("{weather}") {
-        let t = m.update_weather();
-        s = s.replace("{weather}", &t)
-    };
+/// Checks that `config` would run, without updating any block or writing a status.
+pub fn check(config: &Config, registry: &Registry) -> Result<()> {
+    output::from_name(config.output())?;
+    let format = config.parse_format()?;
+    registry.check(config, &format)
+}
 
-    if s.contains("{download}") || s.contains("{upload}") {
-        m.update_net();
-        s = s.replace("{upload}", &m.net_up());
-        s = s.replace("{download}", &m.net_dl());
-    };
+/// What the main loop wakes up for.
+pub(crate) enum Event {
+    /// Some block's output changed.
+    Changed,
+    /// The config file changed or SIGHUP was received.
+    Reload,
+}
 
-    if s.contains("{cpu}") {
-        let t = m.update_cpu();
-        s = s.replace("{cpu}", &t)
-    };
+/// A config with its blocks running.
+struct Running {
+    config: Config,
+    format: Format,
+    blocks: block::Blocks,
+}
 
-    if s.contains("{memory}") {
-        let t = m.update_mem();
-        s = s.replace("{memory}", &t)
-    };
+impl Running {
+    fn start(config: Config, registry: &Registry, events: &Sender<Event>) -> Result<Running> {
+        let format = config.parse_format()?;
+        let blocks = registry.init(&config, &format, events)?;
+        Ok(Running { config, format, blocks })
+    }
+}
 
-    if s.contains("{bat}") {
-        let t = m.update_bat();
-        s = s.replace("{bat}", &t)
-    };
+fn run_output(config: Config, registry: &Registry, output: &mut dyn Output) -> Result<()> {
+    let (tx, events) = channel();
+
+    let mut watcher = reload::Watcher::new(tx.clone())?;
+    watcher.watch(config.files());
+    reload::listen_sighup(tx.clone())?;
 
-    s
+    let mut running = Running::start(config, registry, &tx)?;
+    let actions = Arc::new(Mutex::new(running.blocks.actions(&running.config)));
+    let mut statuscmd = false;
+    let mut last_output = None;
+
+    if output.clicks() {
+        let actions = actions.clone();
+        std::thread::spawn(move || click::listen(std::io::stdin().lock(), &actions));
+    }
+
+    loop {
+        // the listeners stay up for good, as signal handlers can't be unset,
+        // and read whatever actions the current config has
+        if running.config.statuscmd() && !statuscmd {
+            statuscmd::listen_signals(actions.clone())?;
+            if let Some(path) = &running.config.statuscmd_fifo {
+                statuscmd::listen_fifo(path.as_ref(), actions.clone())?;
+            }
+            statuscmd = true;
+        }
+
+        if let Event::Reload = next_event(&events) {
+            match reload_config(&running.config, registry, &tx) {
+                Ok((new, new_actions)) => {
+                    // dropping the old blocks stops their workers
+                    running = new;
+                    *worker::lock(&actions) = new_actions;
+                    last_output = None;
+                    watcher.watch(running.config.files());
+                }
+                Err(e) => eprintln!("Error: keeping the old config, the new one is invalid: {}", e),
+            }
+            // the new blocks send `Changed` once they have output
+            continue;
+        }
+
+        let segments = running.blocks.render(&running.format);
+        if last_output.as_ref() != Some(&segments) {
+            // keep running while the output is unavailable, e.g. while the
+            // X server restarts, the next change tries again
+            match output.write(&segments) {
+                Ok(()) => last_output = Some(segments),
+                Err(e) => eprintln!("Error: could not write the status: {}", e),
+            }
+        }
+    }
 }
 
-pub fn get_config_path() -> Result<PathBuf> {
-    match dirs::home_dir() {
-        Some(mut path) => {
-            path.push(".config/rustystatus/config.toml");
-            Ok(path)
+/// Waits for the next event, coalescing the ones that arrive together.
+fn next_event(events: &Receiver<Event>) -> Event {
+    let mut event = match events.recv() {
+        Ok(e) => e,
+        // the sender in `run_output` keeps the channel open
+        Err(_) => unreachable!(),
+    };
+    if let Event::Reload = event {
+        // editors write a file in several steps, wait for them to finish
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    while let Ok(e) = events.try_recv() {
+        if let Event::Reload = e {
+            event = e;
         }
-        None => Err("missing home directory definition `$HOME`".into()),
     }
+    event
+}
+
+/// Loads the config again and starts its blocks, leaving `old` running on error.
+fn reload_config(
+    old: &Config,
+    registry: &Registry,
+    events: &Sender<Event>,
+) -> Result<(Running, click::Actions)> {
+    let mut config = old.reload()?;
+    // the backend is not switched while running, keep `--output` too
+    config.output = old.output.clone();
+
+    let running = Running::start(config, registry, events)?;
+    let actions = running.blocks.actions(&running.config);
+    Ok((running, actions))
 }
 
-pub fn call(out: &str) -> Result<()> {
-    println!("{}", out);
-    std::process::Command::new("xsetroot")
-        .arg("-name")
-        .arg(out)
-        .output()?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_interval_must_be_positive() {
+        assert_eq!(Config::parse("update_interval = 0.5\n").unwrap().update_interval(), Duration::from_millis(500));
+        assert_eq!(Config::parse("update_interval = 0.1\n").unwrap().update_interval(), Duration::from_millis(100));
+        assert_eq!(Config::parse("").unwrap().update_interval(), Duration::from_secs(1));
+
+        for bad in &["0", "-1.5", "0.0001", "nan", "inf"] {
+            let e = Config::parse(&format!("format = \"{{cpu}}\"\nupdate_interval = {}\n", bad)).err().unwrap();
+            assert_eq!(e.to_string(), "`config.toml` line 2: `update_interval` must be at least 0.1 seconds");
+        }
+    }
+
+    #[test]
+    fn block_intervals_must_be_positive() {
+        let config = Config::parse("[load]\ninterval = 2.5\n").unwrap();
+        assert_eq!(config.block_secs("load", "interval").unwrap(), Some(Duration::from_millis(2500)));
+
+        for bad in &["0", "-1", "0.0001", "nan", "-inf"] {
+            let config = Config::parse(&format!("[load]\ninterval = {}\n", bad)).unwrap();
+            let e = config.block_secs("load", "interval").unwrap_err();
+            assert_eq!(e.to_string(), "`config.toml` line 2: `load.interval` must be at least 0.1 seconds");
+        }
+    }
 }
@@ -0,0 +1,115 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::block::check_placeholder;
+use crate::sys::Fs;
+use crate::{Args, Block, Config};
+
+// {load}          load average over 1 minute, also {load.1}
+// {load.5}        over 5 minutes
+// {load.15}       over 15 minutes
+// {load.running}  tasks running right now
+// {load.tasks}    tasks there are
+
+const PLACEHOLDERS: &[&str] = &["load", "load.1", "load.5", "load.15", "load.running", "load.tasks"];
+
+/// `/proc/loadavg`, e.g. `1.37 0.98 0.71 3/1287 48213`.
+#[derive(Debug, PartialEq, Clone)]
+struct LoadAvg {
+    averages: [f64; 3],
+    running: u64,
+    tasks: u64,
+}
+
+#[derive(Debug)]
+pub struct Load {
+    val: Option<LoadAvg>,
+    fs: Arc<dyn Fs>,
+}
+
+impl Block for Load {
+    fn init(config: &Config) -> crate::Result<Load> {
+        Ok(Load {
+            val: None,
+            fs: config.fs(),
+        })
+    }
+
+    fn update(&mut self) {
+        match self.fs.read(Path::new("/proc/loadavg")) {
+            Ok(s) => {
+                self.val = parse(&s);
+                if self.val.is_none() {
+                    eprintln!("Error: failed parsing `/proc/loadavg`");
+                }
+            }
+            Err(e) => {
+                self.val = None;
+                eprintln!("Error: `/proc/loadavg` {}", e);
+            }
+        }
+    }
+
+    fn output(&self, placeholder: &str, _args: &Args) -> Option<String> {
+        let l = self.val.as_ref()?;
+        match placeholder {
+            "load" | "load.1" => Some(format!("{:.2}", l.averages[0])),
+            "load.5" => Some(format!("{:.2}", l.averages[1])),
+            "load.15" => Some(format!("{:.2}", l.averages[2])),
+            "load.running" => Some(l.running.to_string()),
+            "load.tasks" => Some(l.tasks.to_string()),
+            _ => None,
+        }
+    }
+
+    // the kernel computes it every 5 seconds
+    fn interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(5))
+    }
+
+    fn check_args(&self, placeholder: &str, _args: &Args) -> crate::Result<()> {
+        check_placeholder(placeholder, PLACEHOLDERS)
+    }
+
+    fn placeholders() -> &'static [&'static str] {
+        PLACEHOLDERS
+    }
+}
+
+fn parse(s: &str) -> Option<LoadAvg> {
+    let mut fields = s.split_whitespace();
+    let mut averages = [0.0; 3];
+    for avg in averages.iter_mut() {
+        *avg = fields.next()?.parse().ok()?;
+    }
+    let (running, tasks) = fields.next()?.split_once('/')?;
+    Some(LoadAvg {
+        averages,
+        running: running.parse().ok()?,
+        tasks: tasks.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::{fixture, sampled, MockFs};
+
+    #[test]
+    fn parses_loadavg() {
+        let load: Load = sampled("", fixture("server"));
+        let out: Vec<_> = PLACEHOLDERS.iter().map(|p| load.output(p, &Args::default()).unwrap()).collect();
+        assert_eq!(out, ["51.02", "51.02", "48.77", "40.13", "71", "3312"]);
+    }
+
+    #[test]
+    fn garbage_hides_the_values() {
+        assert_eq!(parse("1.0 2.0"), None);
+        assert_eq!(parse("1.0 2.0 3.0 7 100"), None);
+
+        let fs = MockFs::default();
+        fs.set("/proc/loadavg", "");
+        assert_eq!(sampled::<Load>("", Arc::new(fs)).output("load", &Args::default()), None);
+    }
+}
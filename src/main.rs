@@ -1,25 +1,104 @@
 extern crate rustystatus;
 
-use rustystatus::Config;
-use rustystatus::{run, call};
-use std::time::Duration;
-use std::thread::sleep;
+use rustystatus::{Config, Registry};
+use std::path::PathBuf;
+
+const USAGE: &str = "usage: rustystatus [OPTIONS]
+
+options:
+    --config PATH    read only PATH and its includes instead of layering the
+                     rustystatus/config.toml and hosts/HOSTNAME.toml files of
+                     $XDG_CONFIG_DIRS (/etc/xdg) and $XDG_CONFIG_HOME (~/.config)
+    --output NAME    write to NAME: dwm, stdout, i3bar, lemonbar or tmux
+    --stdout-only    never touch the X root window, write to stdout instead of dwm
+    --once           write a single status line and exit
+    --check          check the config and exit
+    --list-blocks    list the blocks and their placeholders and exit
+    --help           show this message";
+
+#[derive(Default)]
+struct Options {
+    config: Option<PathBuf>,
+    output: Option<String>,
+    stdout_only: bool,
+    once: bool,
+    check: bool,
+    list_blocks: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("`{}` needs a value", arg));
+        match arg.as_str() {
+            "--config" => opts.config = Some(PathBuf::from(value()?)),
+            "--output" => opts.output = Some(value()?),
+            "--stdout-only" => opts.stdout_only = true,
+            "--once" => opts.once = true,
+            "--check" => opts.check = true,
+            "--list-blocks" => opts.list_blocks = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("unknown option `{}`", arg)),
+        }
+    }
+    Ok(opts)
+}
+
+fn fail(err: impl std::fmt::Display) -> ! {
+    eprintln!("Error: {}", err);
+    std::process::exit(1);
+}
 
 fn main() {
-    let config = Config::new().unwrap_or_else(|err| {
-        eprintln!("Error: {}", err);
-        sleep(Duration::from_secs(5));
-        Config::default()
+    let opts = parse_args().unwrap_or_else(|err| {
+        eprintln!("Error: {}\n\n{}", err, USAGE);
+        std::process::exit(2);
     });
+    let registry = Registry::default();
 
-    if let Err(e) = run(config) {
-        eprintln!("Error: {}", e);
+    if opts.list_blocks {
+        for (name, placeholders) in registry.blocks() {
+            println!("{}: {}", name, placeholders.join(", "));
+        }
+        return;
+    }
 
-        // reset xsetroot
-        if let Err(e) = call("") {
-            eprintln!("{:?}", e);
+    let loaded = match &opts.config {
+        Some(path) => Config::load(path),
+        None => Config::new(),
+    };
+    let mut config = match loaded {
+        Ok(c) => c,
+        // a config given on the command line or to be checked has to load
+        Err(e) if opts.config.is_some() || opts.check || opts.once => fail(e),
+        Err(e) => {
+            // keep a bar up, the config is watched and loaded once it's fixed
+            eprintln!("Error: {}, using the default config", e);
+            Config::default()
         }
+    };
+
+    if let Some(name) = &opts.output {
+        config.set_output(name);
+    }
+    if opts.stdout_only && config.output() == "dwm" {
+        config.set_output("stdout");
+    }
 
-        std::process::exit(1);
+    let res = if opts.check {
+        rustystatus::check(&config, &registry)
+    } else if opts.once {
+        rustystatus::run_once(&config, &registry)
+    } else {
+        rustystatus::run_with(config, &registry)
     };
+
+    if let Err(e) = res {
+        fail(e);
+    }
 }
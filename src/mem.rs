@@ -1,40 +1,303 @@
-#[derive(Debug, PartialEq, Clone)]
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::block::check_placeholder;
+use crate::net::{unit_bytes, unknown_unit};
+use crate::sys::Fs;
+use crate::{Args, Block, Config};
+
+// {memory}              used memory, in percent
+// {memory.used}         used memory, like `6.2GiB`
+// {memory.available}    memory that can be had without swapping
+// {memory.total}
+// {memory.cache}        buffers and page cache, which give way when needed
+// {memory.swap}         used swap, in percent
+// {memory.swap_used}
+// {memory.swap_total}
+// {memory.arc}          size of the ZFS ARC
+//
+// {memory.used:unit=GiB} shows the number in GiB without the unit
+//
+// [memory]
+// zfs_arc = true    # count what the ARC can shrink by as available
+
+const PLACEHOLDERS: &[&str] = &[
+    "memory",
+    "memory.used",
+    "memory.available",
+    "memory.total",
+    "memory.cache",
+    "memory.swap",
+    "memory.swap_used",
+    "memory.swap_total",
+    "memory.arc",
+];
+
+const ARCSTATS: &str = "/proc/spl/kstat/zfs/arcstats";
+
+/// In bytes.
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Usage {
+    total: u64,
+    available: u64,
+    cache: u64,
+    swap_total: u64,
+    swap_free: u64,
+    /// `None` without ZFS.
+    arc: Option<u64>,
+}
+
+impl Usage {
+    fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+
+    fn swap_used(&self) -> u64 {
+        self.swap_total.saturating_sub(self.swap_free)
+    }
+}
+
+#[derive(Debug)]
 pub struct Mem {
-    used_mem: Option<f32>,
+    val: Option<Usage>,
+    zfs_arc: bool,
+    fs: Arc<dyn Fs>,
 }
 
-impl Mem {
-    pub fn init() -> Mem {
-        Mem { used_mem: None }
+impl Block for Mem {
+    fn init(config: &Config) -> crate::Result<Mem> {
+        Ok(Mem {
+            val: None,
+            zfs_arc: config.block_bool("memory", "zfs_arc")?.unwrap_or(false),
+            fs: config.fs(),
+        })
     }
 
-    pub fn update(&mut self) {
-        match std::fs::read_to_string("/proc/meminfo") {
+    fn update(&mut self) {
+        match self.fs.read(Path::new("/proc/meminfo")) {
             Ok(s) => {
-                let v: Vec<_> = s
-                    .split('\n')
-                    .filter(|s| s.contains("MemTotal") || s.contains("MemAvailable"))
-                    .map(|s| {
-                        let t: f32 = s
-                            .split_whitespace()
-                            .filter_map(|ss| ss.parse::<f32>().ok())
-                            .nth(0)
-                            .unwrap_or_default();
-                        t
-                    }).collect();
-
-                // memory total = v[0]
-                // memory available = v[1]
-                self.used_mem = Some(100.0 - ((v[1] / v[0]) * 100.0));
+                let arcstats = self.fs.read(Path::new(ARCSTATS)).ok();
+                self.val = usage(&parse_meminfo(&s), arcstats.as_deref().map(parse_arcstats), self.zfs_arc);
+                if self.val.is_none() {
+                    eprintln!("Error: no `MemTotal` in `/proc/meminfo`");
+                }
+            }
+            Err(e) => {
+                self.val = None;
+                eprintln!("Error: {}", e);
             }
-            Err(e) => eprintln!("Error: {}", e),
         }
     }
 
-    pub fn output(&self) -> Option<String> {
-        match &self.used_mem {
-            Some(used_mem) => Some(format!("{:02}", used_mem.round())),
-            _ => None,
+    fn output(&self, placeholder: &str, args: &Args) -> Option<String> {
+        let u = self.val.as_ref()?;
+        let bytes = match placeholder {
+            "memory" => return percent(u.used(), u.total),
+            "memory.swap" => return percent(u.swap_used(), u.swap_total),
+            "memory.used" => u.used(),
+            "memory.available" => u.available,
+            "memory.total" => u.total,
+            "memory.cache" => u.cache,
+            "memory.swap_used" => u.swap_used(),
+            "memory.swap_total" => u.swap_total,
+            "memory.arc" => u.arc?,
+            _ => return None,
+        };
+        match args.get("unit") {
+            Some(unit) => Some(format!("{:.1}", bytes as f32 / unit_bytes(unit)?)),
+            None => Some(human(bytes)),
         }
     }
+
+    fn check_args(&self, placeholder: &str, args: &Args) -> crate::Result<()> {
+        check_placeholder(placeholder, PLACEHOLDERS)?;
+        match args.get("unit") {
+            Some(u) if unit_bytes(u).is_none() => Err(unknown_unit(u).into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn placeholders() -> &'static [&'static str] {
+        PLACEHOLDERS
+    }
+
+    fn config_keys() -> &'static [&'static str] {
+        &["zfs_arc"]
+    }
+}
+
+/// `used` of `total` in percent, `None` when there is nothing, like swap.
+fn percent(used: u64, total: u64) -> Option<String> {
+    if total == 0 {
+        return None;
+    }
+    Some(format!("{:02.0}", used as f64 / total as f64 * 100.0))
+}
+
+/// `bytes` in the largest binary unit that keeps it at 1 or more.
+fn human(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut val = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while val >= 1024.0 && unit + 1 < UNITS.len() {
+        val /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", val, UNITS[unit])
+}
+
+/// The lines of `/proc/meminfo` by name, in bytes.
+///
+/// ```text
+/// MemTotal:       16225872 kB
+/// HugePages_Total:       0
+/// ```
+fn parse_meminfo(s: &str) -> HashMap<&str, u64> {
+    s.lines()
+        .filter_map(|line| {
+            let (name, rest) = line.split_once(':')?;
+            let mut fields = rest.split_whitespace();
+            let val: u64 = fields.next()?.parse().ok()?;
+            match fields.next() {
+                Some("kB") => Some((name, val * 1024)),
+                Some(_) => None,
+                None => Some((name, val)),
+            }
+        })
+        .collect()
+}
+
+/// The counters of the ZFS ARC by name.
+///
+/// ```text
+/// name                            type data
+/// size                            4    103062437888
+/// ```
+fn parse_arcstats(s: &str) -> HashMap<&str, u64> {
+    s.lines()
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            match fields[..] {
+                [name, _, data] => Some((name, data.parse().ok()?)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn usage(meminfo: &HashMap<&str, u64>, arcstats: Option<HashMap<&str, u64>>, zfs_arc: bool) -> Option<Usage> {
+    let get = |name| meminfo.get(name).copied().unwrap_or(0);
+    let cache = get("Buffers") + get("Cached") + get("SReclaimable");
+
+    // kernels before 3.14 have no estimate
+    let mut available = match meminfo.get("MemAvailable") {
+        Some(a) => *a,
+        None => get("MemFree") + cache,
+    };
+
+    let arc = arcstats.as_ref().and_then(|a| a.get("size").copied());
+    if zfs_arc {
+        // the kernel counts the ARC as used, it shrinks down to `c_min`
+        let min = arcstats.as_ref().and_then(|a| a.get("c_min").copied()).unwrap_or(0);
+        available += arc.unwrap_or(0).saturating_sub(min);
+    }
+
+    let total = *meminfo.get("MemTotal")?;
+    Some(Usage {
+        total,
+        available: available.min(total),
+        cache,
+        swap_total: get("SwapTotal"),
+        swap_free: get("SwapFree"),
+        arc,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::{fixture, sampled, MockFs};
+    use crate::Format;
+
+    fn output(mem: &Mem, placeholder: &str) -> Option<String> {
+        let format = Format::parse(&format!("{{{}}}", placeholder)).unwrap();
+        let p = format.placeholders()[0];
+        mem.output(p.name(), p.args())
+    }
+
+    #[test]
+    fn used_is_what_is_not_available() {
+        let mem: Mem = sampled("", fixture("laptop"));
+        // 1 - 9735104 / 16225872
+        assert_eq!(output(&mem, "memory").as_deref(), Some("40"));
+        assert_eq!(output(&mem, "memory.used").as_deref(), Some("6.2GiB"));
+        assert_eq!(output(&mem, "memory.available").as_deref(), Some("9.3GiB"));
+        assert_eq!(output(&mem, "memory.total").as_deref(), Some("15.5GiB"));
+        assert_eq!(output(&mem, "memory.total:unit=GB").as_deref(), Some("16.6"));
+        assert_eq!(output(&mem, "memory.cache").as_deref(), Some("6.9GiB"));
+        assert_eq!(output(&mem, "memory.arc"), None);
+    }
+
+    #[test]
+    fn swap() {
+        let mem: Mem = sampled("", fixture("laptop"));
+        assert_eq!(output(&mem, "memory.swap").as_deref(), Some("02"));
+        assert_eq!(output(&mem, "memory.swap_used").as_deref(), Some("196.8MiB"));
+        assert_eq!(output(&mem, "memory.swap_total").as_deref(), Some("8.0GiB"));
+
+        let mem: Mem = sampled("", fixture("desktop"));
+        assert_eq!(output(&mem, "memory.swap"), None);
+        assert_eq!(output(&mem, "memory.swap_used").as_deref(), Some("0B"));
+    }
+
+    #[test]
+    fn zfs_arc_is_reclaimable_when_asked() {
+        let mem: Mem = sampled("", fixture("server"));
+        assert_eq!(output(&mem, "memory").as_deref(), Some("84"));
+        assert_eq!(output(&mem, "memory.arc").as_deref(), Some("96.0GiB"));
+
+        // 96GiB of ARC down to its 7.9GiB minimum
+        let mem: Mem = sampled("[memory]\nzfs_arc = true\n", fixture("server"));
+        assert_eq!(output(&mem, "memory").as_deref(), Some("49"));
+        assert_eq!(output(&mem, "memory.available").as_deref(), Some("128.1GiB"));
+    }
+
+    #[test]
+    fn missing_lines_dont_panic() {
+        let fs = Arc::new(MockFs::default());
+        fs.set("/proc/meminfo", "MemFree: 100 kB\n");
+        let mem: Mem = sampled("", fs.clone());
+        assert_eq!(output(&mem, "memory"), None);
+
+        // without MemAvailable it is estimated
+        fs.set("/proc/meminfo", "MemTotal: 1000 kB\nMemFree: 100 kB\nCached: 400 kB\n");
+        let mem: Mem = sampled("", fs);
+        assert_eq!(output(&mem, "memory").as_deref(), Some("50"));
+    }
+
+    #[test]
+    fn human_units() {
+        assert_eq!(human(0), "0B");
+        assert_eq!(human(1023), "1023B");
+        assert_eq!(human(1536), "1.5KiB");
+        assert_eq!(human(5 << 40), "5.0TiB");
+        assert_eq!(human(3 << 50), "3072.0TiB");
+    }
+
+    #[test]
+    fn checks_placeholders_and_units() {
+        let mem: Mem = sampled("", fixture("desktop"));
+        let check = |p: &str| {
+            let format = Format::parse(&format!("{{{}}}", p)).unwrap();
+            let p = format.placeholders()[0];
+            mem.check_args(p.name(), p.args())
+        };
+        assert!(check("memory.swap_used:unit=MiB").is_ok());
+        assert!(check("memory.free").is_err());
+        assert!(check("memory.used:unit=pages").is_err());
+    }
 }
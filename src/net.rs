@@ -1,97 +1,161 @@
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Instant;
 use neterror::*;
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::block::check_placeholder;
+use crate::sys::Fs;
+use crate::{Args, Block, Config};
+
+/// Number of updates the speed is averaged over.
+const SAMPLES: usize = 3;
+
+#[derive(Debug)]
 pub struct Net {
+    /// B/s
     avg_recv: Option<f32>,
+    /// B/s
     avg_tran: Option<f32>,
-    recv: f32,
-    tran: f32,
+    /// Bytes received and transmitted until the last update, and when.
+    last: Option<(u64, u64, Instant)>,
     recv_stack: Vec<f32>,
     tran_stack: Vec<f32>,
-    net_time: Instant,
     interface: String,
+    alt_unit: String,
+    show_alt: bool,
+    fs: Arc<dyn Fs>,
 }
 
-impl Net {
-    pub fn init(i: String) -> Net {
-        Net {
+impl Block for Net {
+    fn init(config: &Config) -> crate::Result<Net> {
+        let alt_unit = config.block_str("net", "alt_unit")?.unwrap_or("KiB");
+        if unit_bytes(alt_unit).is_none() {
+            return Err(config.error_at(&["net", "alt_unit"], format!("`net.alt_unit`: {}", unknown_unit(alt_unit))));
+        }
+
+        Ok(Net {
             avg_recv: None,
             avg_tran: None,
-            recv: 0.0,
-            tran: 0.0,
-            recv_stack: vec![0.0, 0.0, 0.0],
-            tran_stack: vec![0.0, 0.0, 0.0],
-            net_time: Instant::now(),
-            interface: i,
-        }
+            last: None,
+            recv_stack: Vec::new(),
+            tran_stack: Vec::new(),
+            interface: config.get_net_interface()?,
+            alt_unit: alt_unit.to_string(),
+            show_alt: false,
+            fs: config.fs(),
+        })
     }
 
-    pub fn update(&mut self) {
-        match read_net_proc(&self.interface) {
-            Ok(i) => {
-                let seconds_passed = self.net_time.elapsed().as_secs() * 1_000_000;
-
-                if let Some(x) = i.get(0) {
-                    self.recv_stack.remove(0);
-                    self.recv_stack
-                        .push((x - self.recv) / seconds_passed as f32);
-                    self.recv = *x;
-                }
-                self.avg_recv = Some(transfer_speed_as_mb(&self.recv_stack));
-
-                if let Some(y) = i.get(8) {
-                    self.tran_stack.remove(0);
-                    self.tran_stack
-                        .push((y - self.tran) / seconds_passed as f32);
-                    self.tran = *y;
-                }
-                self.avg_tran = Some(transfer_speed_as_mb(&self.tran_stack));
-
-                self.net_time = Instant::now();
-            },
+    fn update(&mut self) {
+        match read_net_proc(&*self.fs, &self.interface) {
+            Ok((recv, tran)) => self.sample(recv, tran, Instant::now()),
             Err(e) => {
                 self.avg_recv = None;
                 self.avg_tran = None;
+                self.last = None;
                 eprintln!("Error: {}", e);
             },
         }
     }
 
-    pub fn dl_output(&self) -> Option<String> {
-        match &self.avg_recv {
-            Some(recv) => Some(format!("{:.2}",*recv)),
-            _ => None,
+    // {download:unit=KiB} shows the speed in KiB/s, MB/s is the default
+    fn output(&self, placeholder: &str, args: &Args) -> Option<String> {
+        let val = match placeholder {
+            "upload" => self.avg_tran,
+            _ => self.avg_recv,
+        };
+        let unit = if self.show_alt {
+            &self.alt_unit
+        } else {
+            args.get("unit").unwrap_or("MB")
+        };
+        let unit = unit_bytes(unit)?;
+        val.map(|v| format!("{:.2}", v / unit))
+    }
+
+    fn check_args(&self, placeholder: &str, args: &Args) -> crate::Result<()> {
+        check_placeholder(placeholder, &["download", "upload"])?;
+        match args.get("unit") {
+            Some(u) if unit_bytes(u).is_none() => Err(unknown_unit(u).into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn action(&mut self, _placeholder: &str, action: &str) -> crate::Result<()> {
+        match action {
+            "toggle_unit" => self.show_alt = !self.show_alt,
+            _ => return Err(format!("unknown action `{}`, expected `toggle_unit`", action).into()),
         }
+        Ok(())
+    }
+
+    fn config_keys() -> &'static [&'static str] {
+        &["interface", "alt_unit"]
     }
-    pub fn up_output(&self) -> Option<String> {
-        match &self.avg_tran {
-            Some(tran) => Some(format!("{:.2}", *tran)),
-            _ => None,
+}
+
+impl Net {
+    /// Adds the speed since the last sample to the averages. The first sample
+    /// only sets where to count from, so there is no speed until the second.
+    fn sample(&mut self, recv: u64, tran: u64, now: Instant) {
+        if let Some((last_recv, last_tran, last_time)) = self.last {
+            let secs = now.duration_since(last_time).as_secs_f32();
+            // the counters start over when the interface is brought up again
+            if let (true, Some(r), Some(t)) = (secs > 0.0, recv.checked_sub(last_recv), tran.checked_sub(last_tran)) {
+                push(&mut self.recv_stack, r as f32 / secs);
+                push(&mut self.tran_stack, t as f32 / secs);
+                self.avg_recv = Some(average(&self.recv_stack));
+                self.avg_tran = Some(average(&self.tran_stack));
+            }
         }
+        self.last = Some((recv, tran, now));
     }
 }
 
-pub fn read_net_proc(interface: &str) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
-    let net_info = std::fs::read_to_string("/proc/net/dev")?;
+/// Bytes received and transmitted by `interface`, from `/proc/net/dev`.
+pub fn read_net_proc(fs: &dyn Fs, interface: &str) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let net_info = fs.read(Path::new("/proc/net/dev"))?;
 
-    let vals: Vec<_> = net_info
-        .split('\n')
-        .filter(|s| s.contains(interface))
-        .collect::<String>()
-        .trim()
-        .split_whitespace()
-        .filter_map(|s| s.parse::<f32>().ok())
-        .collect();
+    //   face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets ...
+    // wlp3s0: 2931846121 2387190    0    0    0     0          0         0 184829377  931241 ...
+    let line = net_info
+        .lines()
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, _)| name.trim() == interface)
+        .ok_or_else(|| NetError::NoInterface(interface.to_string()))?;
 
-    if vals.is_empty() {
-        Err(NetError::EmptyVec.into())
-    } else {
-        Ok(vals)
+    let vals: Vec<u64> = line.1.split_whitespace().map(|s| s.parse()).collect::<Result<_, _>>()?;
+    match (vals.first(), vals.get(8)) {
+        (Some(recv), Some(tran)) => Ok((*recv, *tran)),
+        _ => Err(NetError::ReadProc.into()),
     }
 }
 
-fn transfer_speed_as_mb(v: &[f32]) -> f32 {
+pub(crate) fn unknown_unit(unit: &str) -> String {
+    format!("unknown unit `{}`, expected one of B, KB, MB, GB, KiB, MiB, GiB", unit)
+}
+
+pub(crate) fn unit_bytes(unit: &str) -> Option<f32> {
+    match unit {
+        "B" => Some(1.0),
+        "KB" => Some(1_000.0),
+        "MB" => Some(1_000_000.0),
+        "GB" => Some(1_000_000_000.0),
+        "KiB" => Some(1024.0),
+        "MiB" => Some(1024.0 * 1024.0),
+        "GiB" => Some(1024.0 * 1024.0 * 1024.0),
+        _ => None,
+    }
+}
+
+fn push(stack: &mut Vec<f32>, val: f32) {
+    if stack.len() == SAMPLES {
+        stack.remove(0);
+    }
+    stack.push(val);
+}
+
+fn average(v: &[f32]) -> f32 {
     let sum: f32 = v.iter().sum();
     let len: f32 = v.len() as f32;
     sum / len
@@ -102,22 +166,98 @@ mod neterror {
 
     #[derive(Debug)]
     pub enum NetError {
-        EmptyVec,
+        NoInterface(String),
+        ReadProc,
     }
 
     impl std::error::Error for NetError {
         fn description(&self) -> &str {
             match *self {
-                NetError::EmptyVec => "vector is empty",
+                NetError::NoInterface(_) => "interface not found in `/proc/net/dev`",
+                NetError::ReadProc => "failed parsing `/proc/net/dev`",
             }
         }
     }
 
     impl fmt::Display for NetError {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            match *self {
-                NetError::EmptyVec => f.write_str("vec is empty"),
+            match self {
+                NetError::NoInterface(i) => write!(f, "interface `{}` not found in `/proc/net/dev`", i),
+                NetError::ReadProc => f.write_str("failed parsing `/proc/net/dev`"),
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::fixture;
+    use std::time::Duration;
+
+    fn net(unit: &str) -> Net {
+        let config = Config::parse(&format!("[net]\ninterface = \"wlp3s0\"\nalt_unit = \"{}\"", unit)).unwrap();
+        Net::init(&config.with_fs(fixture("laptop"))).unwrap()
+    }
+
+    #[test]
+    fn reads_the_interface_by_name() {
+        let laptop = fixture("laptop");
+        assert_eq!(read_net_proc(laptop.as_ref(), "wlp3s0").unwrap(), (2931846121, 184829377));
+        assert_eq!(read_net_proc(laptop.as_ref(), "lo").unwrap(), (18374421, 18374421));
+        assert!(read_net_proc(laptop.as_ref(), "wlp3s").is_err());
+        assert!(read_net_proc(fixture("desktop").as_ref(), "wlp3s0").is_err());
+    }
+
+    #[test]
+    fn no_speed_before_the_second_sample() {
+        let mut net = net("KiB");
+        net.update();
+        assert_eq!(net.avg_recv, None);
+        assert_eq!(net.output("download", &Args::default()), None);
+    }
+
+    #[test]
+    fn speed_is_averaged() {
+        let mut net = net("KiB");
+        let t = Instant::now();
+        net.sample(1_000_000, 0, t);
+        net.sample(3_000_000, 500_000, t + Duration::from_secs(2));
+        assert_eq!(net.output("download", &Args::default()).as_deref(), Some("1.00"));
+        assert_eq!(net.output("upload", &Args::default()).as_deref(), Some("0.25"));
+
+        net.sample(3_000_000, 500_000, t + Duration::from_millis(2500));
+        assert_eq!(net.output("download", &Args::default()).as_deref(), Some("0.50"));
+
+        net.show_alt = true;
+        assert_eq!(net.output("upload", &Args::default()).as_deref(), Some("122.07"));
+    }
+
+    #[test]
+    fn counter_reset_is_skipped() {
+        let mut net = net("KiB");
+        let t = Instant::now();
+        net.sample(5_000_000, 0, t);
+        net.sample(6_000_000, 0, t + Duration::from_secs(1));
+        net.sample(10_000, 0, t + Duration::from_secs(2));
+        assert_eq!(net.avg_recv, Some(1_000_000.0));
+        net.sample(1_010_000, 0, t + Duration::from_secs(3));
+        assert_eq!(net.avg_recv, Some(1_000_000.0));
+    }
+
+    #[test]
+    fn checks_placeholders_and_units() {
+        let net = net("KiB");
+        let check = |p: &str| {
+            let format = crate::Format::parse(&format!("{{{}}}", p)).unwrap();
+            let p = format.placeholders()[0];
+            net.check_args(p.name(), p.args())
+        };
+        assert!(check("upload:unit=KiB").is_ok());
+        assert_eq!(
+            check("download.zz").unwrap_err().to_string(),
+            "expected one of `download`, `upload`, did you mean `download`?"
+        );
+        assert!(check("download:unit=pages").is_err());
+    }
+}
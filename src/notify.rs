@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+use crate::Result;
+
+/// How urgent a notification is, as in the freedesktop notification spec.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Urgency {
+    Normal = 1,
+    Critical = 2,
+}
+
+/// Something that shows notifications.
+pub(crate) trait Notify: Send {
+    fn send(&mut self, summary: &str, body: &str, urgency: Urgency) -> Result<()>;
+}
+
+/// Desktop notifications over the `org.freedesktop.Notifications` D-Bus
+/// interface of the session bus.
+///
+/// The connection is opened on first use and kept open. Every notification
+/// replaces the previous one, so a falling battery doesn't stack them up.
+#[derive(Default)]
+pub(crate) struct Notifier {
+    conn: Option<Connection>,
+    id: u32,
+}
+
+impl Notify for Notifier {
+    fn send(&mut self, summary: &str, body: &str, urgency: Urgency) -> Result<()> {
+        if self.try_send(summary, body, urgency).is_ok() {
+            return Ok(());
+        }
+
+        // the bus might have restarted, retry once on a fresh connection
+        self.conn = None;
+        self.try_send(summary, body, urgency)
+    }
+}
+
+impl Notifier {
+    pub(crate) fn new() -> Notifier {
+        Notifier { conn: None, id: 0 }
+    }
+
+    fn try_send(&mut self, summary: &str, body: &str, urgency: Urgency) -> Result<()> {
+        if self.conn.is_none() {
+            self.conn = Some(Connection::session()?);
+        }
+
+        if let Some(conn) = &self.conn {
+            let mut hints = HashMap::new();
+            hints.insert("urgency", Value::from(urgency as u8));
+
+            let reply = conn.call_method(
+                Some("org.freedesktop.Notifications"),
+                "/org/freedesktop/Notifications",
+                Some("org.freedesktop.Notifications"),
+                "Notify",
+                &("rustystatus", self.id, "", summary, body, Vec::<&str>::new(), hints, -1i32),
+            )?;
+            self.id = reply.body().deserialize()?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use std::io::Write;
+
+use crate::style::spans;
+use crate::xroot::RootName;
+use crate::Result;
+
+/// A piece of the status line, either literal text from `format` or the
+/// output of a placeholder.
+///
+/// Colors in `text` are status2d markup, `^c#rrggbb^` and `^d^`, which every
+/// backend but dwm translates to its own.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Segment {
+    pub text: String,
+    /// Name of the block that rendered the text, `None` for literal text.
+    pub name: Option<String>,
+    /// The placeholder the text was rendered for.
+    pub instance: Option<String>,
+    /// Marker byte of the block for dwm's statuscmd patch, if enabled.
+    pub signal: Option<u8>,
+}
+
+/// Where the status line is written to.
+pub trait Output {
+    fn write(&mut self, segments: &[Segment]) -> Result<()>;
+
+    /// Whether the bar reports clicks on stdin, in the i3bar click event format.
+    fn clicks(&self) -> bool {
+        false
+    }
+
+    /// Clears the status when rustystatus exits with an error.
+    fn clear(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Names accepted by `from_name`.
+pub const OUTPUTS: &[&str] = &["dwm", "stdout", "i3bar", "lemonbar", "tmux"];
+
+/// The output backend called `name`.
+pub fn from_name(name: &str) -> Result<Box<dyn Output>> {
+    match name {
+        "dwm" => Ok(Box::new(Dwm(RootName::new()))),
+        "stdout" => Ok(Box::new(Stdout)),
+        "i3bar" | "swaybar" => Ok(Box::new(I3bar { started: false })),
+        "lemonbar" => Ok(Box::new(Lemonbar)),
+        "tmux" => Ok(Box::new(Tmux)),
+        _ => Err(format!("unknown output `{}`, expected one of {}", name, OUTPUTS.join(", ")).into()),
+    }
+}
+
+/// Joins `segments`, escaping their text with `escape` and translating
+/// status2d colors with `color`, which gets `None` to reset to the default.
+fn join(segments: &[Segment], escape: fn(&str) -> String, color: fn(Option<&str>) -> String) -> String {
+    let mut line = String::new();
+    let mut current = None;
+    let mut color_state = None;
+
+    for span in segments.iter().flat_map(|s| spans(&s.text, &mut color_state)) {
+        if span.color != current {
+            line.push_str(&color(span.color));
+            current = span.color;
+        }
+        line.push_str(&escape(span.text));
+    }
+
+    if current.is_some() {
+        line.push_str(&color(None));
+    }
+    line
+}
+
+fn print_line(line: &str) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "{}", line)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// The name of the X root window, shown by dwm. Colors need dwm's status2d patch.
+pub struct Dwm(RootName);
+
+impl Output for Dwm {
+    fn write(&mut self, segments: &[Segment]) -> Result<()> {
+        let mut status = String::new();
+        for s in segments {
+            if let Some(n) = s.signal {
+                status.push(char::from(n));
+            }
+            status.push_str(&s.text);
+        }
+        self.0.set(&status)
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.0.set("")
+    }
+}
+
+/// One plain line on stdout per update, without colors.
+pub struct Stdout;
+
+impl Stdout {
+    fn line(segments: &[Segment]) -> String {
+        join(segments, |s| s.to_string(), |_| String::new())
+    }
+}
+
+impl Output for Stdout {
+    fn write(&mut self, segments: &[Segment]) -> Result<()> {
+        print_line(&Stdout::line(segments))
+    }
+}
+
+/// The i3bar protocol, also spoken by swaybar: a header followed by an
+/// infinite JSON array with one array of blocks per update.
+pub struct I3bar {
+    started: bool,
+}
+
+impl I3bar {
+    /// The blocks of `segments` as an element of the array, after the header
+    /// and the opening `[` the first time.
+    fn lines(&mut self, segments: &[Segment]) -> String {
+        let mut lines = String::new();
+        if !self.started {
+            lines.push_str("{\"version\":1,\"click_events\":true}\n[\n");
+            self.started = true;
+        }
+
+        // every segment is a block without separators, so the bar looks like `format`
+        // and one more wherever the color changes
+        let mut color_state = None;
+        let blocks: Vec<_> = segments
+            .iter()
+            .flat_map(|s| spans(&s.text, &mut color_state).into_iter().map(move |span| (s, span)))
+            .map(|(s, span)| {
+                let mut block = serde_json::json!({
+                    "full_text": span.text,
+                    "separator": false,
+                    "separator_block_width": 0,
+                });
+                if let Some(color) = span.color {
+                    block["color"] = color.into();
+                }
+                if let Some(name) = &s.name {
+                    block["name"] = name.as_str().into();
+                }
+                if let Some(instance) = &s.instance {
+                    block["instance"] = instance.as_str().into();
+                }
+                block
+            })
+            .collect();
+
+        lines.push_str(&format!("{},", serde_json::Value::Array(blocks)));
+        lines
+    }
+}
+
+impl Output for I3bar {
+    fn write(&mut self, segments: &[Segment]) -> Result<()> {
+        print_line(&self.lines(segments))
+    }
+
+    fn clicks(&self) -> bool {
+        true
+    }
+}
+
+/// A line for lemonbar, with colors as `%{F#rrggbb}` and `%` escaped from its markup.
+pub struct Lemonbar;
+
+impl Lemonbar {
+    fn line(segments: &[Segment]) -> String {
+        let color = |c: Option<&str>| match c {
+            Some(c) => format!("%{{F{}}}", c),
+            None => String::from("%{F-}"),
+        };
+        join(segments, |s| s.replace('%', "%%"), color)
+    }
+}
+
+impl Output for Lemonbar {
+    fn write(&mut self, segments: &[Segment]) -> Result<()> {
+        print_line(&Lemonbar::line(segments))
+    }
+}
+
+/// A line for tmux' `#()`, with colors as `#[fg=#rrggbb]` and `#` escaped from its formats.
+pub struct Tmux;
+
+impl Tmux {
+    fn line(segments: &[Segment]) -> String {
+        let color = |c: Option<&str>| match c {
+            Some(c) => format!("#[fg={}]", c),
+            None => String::from("#[default]"),
+        };
+        join(segments, |s| s.replace('#', "##"), color)
+    }
+}
+
+impl Output for Tmux {
+    fn write(&mut self, segments: &[Segment]) -> Result<()> {
+        print_line(&Tmux::line(segments))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, name: Option<&str>) -> Segment {
+        Segment {
+            text: text.to_string(),
+            name: name.map(|n| n.to_string()),
+            instance: name.map(|n| n.to_string()),
+            signal: None,
+        }
+    }
+
+    /// Literal text with a `%`, a block in red with a `#`, and more text.
+    fn segments() -> Vec<Segment> {
+        vec![
+            segment("50% ", None),
+            segment("^c#ff0000^#1^d^", Some("cpu")),
+            segment(" end", None),
+        ]
+    }
+
+    #[test]
+    fn stdout_drops_colors() {
+        assert_eq!(Stdout::line(&segments()), "50% #1 end");
+    }
+
+    #[test]
+    fn lemonbar_escapes_percent() {
+        assert_eq!(Lemonbar::line(&segments()), "50%% %{F#ff0000}#1%{F-} end");
+        // left at the end
+        assert_eq!(Lemonbar::line(&[segment("^c#00ff00^ok", None)]), "%{F#00ff00}ok%{F-}");
+    }
+
+    #[test]
+    fn tmux_escapes_hash() {
+        assert_eq!(Tmux::line(&segments()), "50% #[fg=#ff0000]##1#[default] end");
+    }
+
+    #[test]
+    fn i3bar_header_then_infinite_array() {
+        let mut i3bar = I3bar { started: false };
+        let blocks = concat!(
+            r#"[{"full_text":"50% ","separator":false,"separator_block_width":0},"#,
+            r##"{"color":"#ff0000","full_text":"#1","instance":"cpu","name":"cpu","separator":false,"separator_block_width":0},"##,
+            r#"{"full_text":" end","separator":false,"separator_block_width":0}],"#,
+        );
+        assert_eq!(
+            i3bar.lines(&segments()),
+            format!("{}\n[\n{}", r#"{"version":1,"click_events":true}"#, blocks)
+        );
+        assert_eq!(i3bar.lines(&segments()), blocks);
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use inotify::{Inotify, WatchDescriptor, WatchMask, Watches};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+
+use crate::worker::lock;
+use crate::{Event, Result};
+
+/// Sends `Event::Reload` whenever one of the watched config files is written.
+///
+/// Directories are watched rather than the files themselves, as editors often
+/// save by writing a new file and renaming it over the old one, and a file
+/// that doesn't exist yet may be created.
+pub(crate) struct Watcher {
+    watches: Watches,
+    // watched file names by the directory they're in
+    files: Arc<Mutex<HashMap<WatchDescriptor, Vec<OsString>>>>,
+}
+
+impl Watcher {
+    pub(crate) fn new(events: Sender<Event>) -> Result<Watcher> {
+        let mut inotify = Inotify::init()?;
+        let watcher = Watcher {
+            watches: inotify.watches(),
+            files: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        let files = watcher.files.clone();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            loop {
+                let evs = match inotify.read_events_blocking(&mut buf) {
+                    Ok(evs) => evs,
+                    Err(e) => {
+                        eprintln!("Error: watching the config stopped: {}", e);
+                        return;
+                    }
+                };
+
+                let files = lock(&files);
+                let changed = evs.into_iter().any(|e| match (files.get(&e.wd), e.name) {
+                    (Some(names), Some(name)) => names.iter().any(|n| n == name),
+                    _ => false,
+                });
+                drop(files);
+
+                if changed && events.send(Event::Reload).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(watcher)
+    }
+
+    /// Watches `paths` instead of the files watched before.
+    ///
+    /// Files in directories that don't exist are skipped.
+    pub(crate) fn watch(&mut self, paths: &[PathBuf]) {
+        // adding a directory again gives its old descriptor back
+        let mut new: HashMap<WatchDescriptor, Vec<OsString>> = HashMap::new();
+        for path in paths {
+            let (dir, name) = match (path.parent(), path.file_name()) {
+                (Some(d), Some(n)) if !d.as_os_str().is_empty() => (d, n),
+                (_, Some(n)) => (Path::new("."), n),
+                _ => continue,
+            };
+            if !dir.is_dir() {
+                continue;
+            }
+
+            let mask = WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE;
+            match self.watches.add(dir, mask) {
+                Ok(wd) => new.entry(wd).or_default().push(name.to_os_string()),
+                Err(e) => eprintln!("Error: could not watch `{}`: {}", dir.display(), e),
+            }
+        }
+
+        let mut files = lock(&self.files);
+        for wd in files.keys() {
+            if !new.contains_key(wd) {
+                let _ = self.watches.remove(wd.clone());
+            }
+        }
+        *files = new;
+    }
+}
+
+/// Sends `Event::Reload` on every SIGHUP.
+pub(crate) fn listen_sighup(events: Sender<Event>) -> Result<()> {
+    let mut signals = Signals::new([SIGHUP])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            if events.send(Event::Reload).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
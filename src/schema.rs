@@ -0,0 +1,296 @@
+use std::path::PathBuf;
+
+use crate::click::BUTTONS;
+use crate::layers::Source;
+use crate::{Config, Result};
+
+/// Top level keys of the config besides the block tables, as in `Config`.
+pub(crate) const CONFIG_KEYS: &[&str] = &[
+    "format",
+    "weather_apikey",
+    "weather_city",
+    "net_interface",
+    "update_interval",
+    "output",
+    "statuscmd",
+    "statuscmd_fifo",
+    "fs_root",
+    "include",
+];
+
+/// Keys every block table may have, on top of the ones the block reads itself.
+pub(crate) const BLOCK_KEYS: &[&str] = &["interval", "icon", "color", "thresholds", "on_click", "signal"];
+
+/// Checks that every top level key of `config` is known and every table is
+/// one of `blocks`, given as block name and the keys it reads.
+pub(crate) fn validate(config: &Config, blocks: &[(&str, &[&str])]) -> Result<()> {
+    let mut names: Vec<_> = config.blocks.keys().collect();
+    names.sort();
+
+    for name in names {
+        let keys = match blocks.iter().find(|(b, _)| b == name) {
+            Some((_, keys)) => keys,
+            None => {
+                let known = CONFIG_KEYS.iter().chain(blocks.iter().map(|(b, _)| b));
+                let msg = format!("unknown key `{}`{}", name, suggest(name, known));
+                return Err(config.error_at(&[name], msg));
+            }
+        };
+
+        let table = match config.block(name) {
+            Some(t) => t,
+            None => return Err(config.error_at(&[name], format!("`{}` must be a table", name))),
+        };
+
+        let mut table_keys: Vec<_> = table.keys().collect();
+        table_keys.sort();
+        for key in table_keys {
+            if !BLOCK_KEYS.contains(&key.as_str()) && !keys.contains(&key.as_str()) {
+                let known = BLOCK_KEYS.iter().chain(keys.iter());
+                let msg = format!("unknown key `{}.{}`{}", name, key, suggest(key, known));
+                return Err(config.error_at(&[name, key], msg));
+            }
+        }
+
+        if let Some(on_click) = table.get("on_click") {
+            validate_on_click(config, name, on_click)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the `on_click` table of block `name`, whether the block is in
+/// `format` or not.
+fn validate_on_click(config: &Config, name: &str, on_click: &toml::Value) -> Result<()> {
+    let table = match on_click {
+        toml::Value::Table(t) => t,
+        _ => {
+            let msg = format!("`{}.on_click` must be a table", name);
+            return Err(config.error_at(&[name, "on_click"], msg));
+        }
+    };
+
+    let mut buttons: Vec<_> = table.iter().collect();
+    buttons.sort_by_key(|(b, _)| b.as_str());
+    for (button, action) in buttons {
+        if !BUTTONS.iter().any(|(b, _)| b == button) {
+            let names: Vec<_> = BUTTONS.iter().map(|(b, _)| *b).collect();
+            let msg = format!(
+                "unknown button `{}.on_click.{}`, expected one of {}",
+                name,
+                button,
+                names.join(", ")
+            );
+            return Err(config.error_at(&[name, "on_click", button], msg));
+        }
+        if !action.is_str() {
+            let msg = format!("`{}.on_click.{}` must be a string", name, button);
+            return Err(config.error_at(&[name, "on_click", button], msg));
+        }
+    }
+    Ok(())
+}
+
+/// `, did you mean `x`?` with the name of `known` closest to `name`, if any
+/// is close enough to be a typo.
+pub(crate) fn suggest<'a>(name: &str, known: impl Iterator<Item = &'a &'a str>) -> String {
+    let best = known
+        .map(|k| (distance(name, k), *k))
+        .filter(|(d, k)| {
+            // a typo, or a longer or shorter name for the same thing like `time`
+            *d <= (name.len() / 3).max(1)
+                || (k.len() >= 3 && name.contains(k))
+                || (name.len() >= 3 && k.contains(name))
+        })
+        .min();
+    match best {
+        Some((_, k)) => format!(", did you mean `{}`?", k),
+        None => String::new(),
+    }
+}
+
+/// Levenshtein distance of `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == *cb { prev } else { 1 + prev.min(cur).min(row[j]) };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// The file and line that sets `key`, a path of table names and the key,
+/// looking at the files that were merged last first.
+///
+/// Lines are matched by their key rather than parsed, which is enough to
+/// point at a typo.
+pub(crate) fn locate(sources: &[Source], key: &[&str]) -> Option<(PathBuf, usize)> {
+    for source in sources.iter().rev() {
+        let mut section: Vec<String> = Vec::new();
+
+        for (i, line) in source.text.lines().enumerate() {
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            let path = if line.starts_with('[') {
+                let header = line.trim_start_matches('[');
+                let header = header.split(']').next().unwrap_or("");
+                section = split_key(header);
+                section.clone()
+            } else if let Some((k, _)) = line.split_once('=') {
+                section.iter().cloned().chain(split_key(k)).collect()
+            } else {
+                continue;
+            };
+
+            if path.len() >= key.len() && path.iter().zip(key).all(|(p, k)| p == k) {
+                return Some((source.path.clone(), i + 1));
+            }
+        }
+    }
+    None
+}
+
+/// Parts of a dotted TOML key, without quotes.
+fn split_key(key: &str) -> Vec<String> {
+    key.split('.')
+        .map(|k| k.trim().trim_matches('"').trim_matches('\'').to_string())
+        .collect()
+}
+
+mod configerror {
+    use std::error::Error;
+    use std::fmt;
+    use std::path::PathBuf;
+
+    /// An invalid config value, with the line that sets it if it was found.
+    #[derive(Debug)]
+    pub struct ConfigError {
+        location: Option<(PathBuf, usize)>,
+        msg: String,
+    }
+
+    impl ConfigError {
+        pub fn new(location: Option<(PathBuf, usize)>, msg: String) -> ConfigError {
+            ConfigError { location, msg }
+        }
+    }
+
+    impl Error for ConfigError {}
+
+    impl fmt::Display for ConfigError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match &self.location {
+                Some((path, line)) => write!(f, "`{}` line {}: {}", path.display(), line, self.msg),
+                None => write!(f, "{}", self.msg),
+            }
+        }
+    }
+}
+
+pub(crate) use configerror::ConfigError;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(path: &str, text: &str) -> Source {
+        Source {
+            path: PathBuf::from(path),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn locates_keys() {
+        let text = "# cpu.color = \"#000000\"
+format = \"{cpu}\"
+cpu.color = \"#ffffff\"
+
+[cpu]
+\"icon\" = \"C\"
+'signal' = 3
+
+[cpu.on_click]
+1 = \"refresh\"
+
+[ bat ]
+# interval = 1
+interval = 2
+";
+        let sources = [source("config.toml", text)];
+        let line = |key: &[&str]| locate(&sources, key).map(|(_, line)| line);
+
+        assert_eq!(line(&["format"]), Some(2));
+        assert_eq!(line(&["cpu", "color"]), Some(3));
+        assert_eq!(line(&["cpu", "icon"]), Some(6));
+        assert_eq!(line(&["cpu", "signal"]), Some(7));
+        assert_eq!(line(&["cpu", "on_click"]), Some(9));
+        assert_eq!(line(&["cpu", "on_click", "1"]), Some(10));
+        assert_eq!(line(&["bat"]), Some(12));
+        assert_eq!(line(&["bat", "interval"]), Some(14));
+        assert_eq!(line(&["bat", "color"]), None);
+    }
+
+    #[test]
+    fn the_last_file_setting_a_key_wins() {
+        let sources = [
+            source("base.toml", "[cpu]\ninterval = 1\n"),
+            source("config.toml", "format = \"{cpu}\"\n\n[cpu]\ninterval = 2\n"),
+        ];
+        assert_eq!(locate(&sources, &["cpu", "interval"]), Some((PathBuf::from("config.toml"), 4)));
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        let blocks = ["datetime", "weather", "cpu", "bat"];
+        assert_eq!(suggest("net_interfaces", CONFIG_KEYS.iter()), ", did you mean `net_interface`?");
+        assert_eq!(suggest("time", blocks.iter()), ", did you mean `datetime`?");
+        assert_eq!(suggest("cpus", blocks.iter()), ", did you mean `cpu`?");
+        assert_eq!(suggest("volume", blocks.iter()), "");
+    }
+
+    #[test]
+    fn unknown_keys_point_at_their_line() {
+        let blocks: &[(&str, &[&str])] = &[("cpu", &[]), ("datetime", &["clocks"])];
+        let check = |text| validate(&Config::parse(text).unwrap(), blocks).unwrap_err().to_string();
+
+        assert_eq!(
+            check("format = \"{cpu}\"\n\n[cpu]\ncolour = \"#ffffff\"\n"),
+            "`config.toml` line 4: unknown key `cpu.colour`, did you mean `color`?"
+        );
+        assert_eq!(
+            check("format = \"{cpu}\"\n[time]\n"),
+            "`config.toml` line 2: unknown key `time`, did you mean `datetime`?"
+        );
+    }
+
+    #[test]
+    fn on_click_is_checked_for_blocks_not_in_format() {
+        let blocks: &[(&str, &[&str])] = &[("cpu", &[]), ("weather", &[])];
+        let check = |text| validate(&Config::parse(text).unwrap(), blocks).map_err(|e| e.to_string());
+
+        assert!(check("format = \"{cpu}\"\n[weather.on_click]\nleft = \"refresh\"\n").is_ok());
+        assert_eq!(
+            check("format = \"{cpu}\"\n\n[weather.on_click]\nlefft = \"refresh\"\n").unwrap_err(),
+            "`config.toml` line 4: unknown button `weather.on_click.lefft`, expected one of left, middle, right, scroll_up, scroll_down"
+        );
+        assert_eq!(
+            check("[cpu.on_click]\nleft = 1\n").unwrap_err(),
+            "`config.toml` line 2: `cpu.on_click.left` must be a string"
+        );
+        assert_eq!(
+            check("[cpu]\non_click = \"refresh\"\n").unwrap_err(),
+            "`config.toml` line 2: `cpu.on_click` must be a table"
+        );
+    }
+}
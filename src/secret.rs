@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+// Any string in the config can be read from somewhere else instead, so that
+// the file itself holds no secrets:
+//
+// [weather]
+// apikey = "env:OWM_KEY"             # environment variable
+// apikey = "file:~/.secrets/owm"     # first line of a file
+// apikey = "cmd:pass show owm"       # first line of a command's output
+//
+// The values are resolved once when the config is loaded. Errors name the key
+// and the source but never the value, so it doesn't end up in a log.
+
+/// Replaces every `env:`, `file:` and `cmd:` string in `table` by what it
+/// points to.
+///
+/// On error returns the path of the key that failed and what went wrong.
+pub(crate) fn resolve(table: &mut toml::value::Table) -> Result<(), (Vec<String>, String)> {
+    for (key, val) in table.iter_mut() {
+        resolve_value(val).map_err(|(mut path, e)| {
+            path.insert(0, key.to_string());
+            (path, e)
+        })?;
+    }
+    Ok(())
+}
+
+fn resolve_value(val: &mut toml::Value) -> Result<(), (Vec<String>, String)> {
+    match val {
+        toml::Value::String(s) => {
+            if let Some(v) = lookup(s).map_err(|e| (Vec::new(), e))? {
+                *s = v;
+            }
+            Ok(())
+        }
+        toml::Value::Array(a) => a.iter_mut().try_for_each(resolve_value),
+        toml::Value::Table(t) => resolve(t),
+        _ => Ok(()),
+    }
+}
+
+/// The value `s` points to, `None` if it is a plain string.
+fn lookup(s: &str) -> Result<Option<String>, String> {
+    if let Some(name) = s.strip_prefix("env:") {
+        match std::env::var(name) {
+            Ok(v) => Ok(Some(v)),
+            Err(_) => Err(format!("environment variable `{}` is not set", name)),
+        }
+    } else if let Some(path) = s.strip_prefix("file:") {
+        let path = expand_home(path);
+        match std::fs::read_to_string(&path) {
+            Ok(v) => Ok(Some(first_line(&v))),
+            Err(e) => Err(format!("could not read `{}`: {}", path.display(), e)),
+        }
+    } else if let Some(cmd) = s.strip_prefix("cmd:") {
+        // stderr is left alone, for the command to ask for a passphrase
+        let out = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| format!("could not run `{}`: {}", cmd, e))?;
+        if !out.status.success() {
+            return Err(format!("`{}` failed with {}", cmd, out.status));
+        }
+        let v = String::from_utf8(out.stdout).map_err(|_| format!("`{}` printed invalid UTF-8", cmd))?;
+        Ok(Some(first_line(&v)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// `~/path` relative to the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn first_line(s: &str) -> String {
+    s.lines().next().unwrap_or("").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(text: &str) -> toml::value::Table {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn env() {
+        std::env::set_var("RUSTYSTATUS_TEST_KEY", "hunter2");
+        std::env::remove_var("RUSTYSTATUS_TEST_UNSET");
+        assert_eq!(lookup("env:RUSTYSTATUS_TEST_KEY"), Ok(Some(String::from("hunter2"))));
+        assert_eq!(
+            lookup("env:RUSTYSTATUS_TEST_UNSET"),
+            Err(String::from("environment variable `RUSTYSTATUS_TEST_UNSET` is not set"))
+        );
+        assert_eq!(lookup("environment"), Ok(None));
+    }
+
+    #[test]
+    fn file() {
+        let path = std::env::temp_dir().join(format!("rustystatus-{}-secret", std::process::id()));
+        std::fs::write(&path, "hunter2\nsecond line\n").unwrap();
+        assert_eq!(lookup(&format!("file:{}", path.display())), Ok(Some(String::from("hunter2"))));
+        std::fs::remove_file(&path).unwrap();
+
+        let e = lookup(&format!("file:{}", path.display())).unwrap_err();
+        assert!(e.starts_with(&format!("could not read `{}`", path.display())), "{}", e);
+    }
+
+    #[test]
+    fn file_in_home() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_home("~/.secrets/owm"), home.join(".secrets/owm"));
+        assert_eq!(expand_home("/etc/owm"), PathBuf::from("/etc/owm"));
+        assert_eq!(expand_home("~user/owm"), PathBuf::from("~user/owm"));
+    }
+
+    #[test]
+    fn cmd() {
+        assert_eq!(lookup("cmd:printf 'hunter2\\nmore'"), Ok(Some(String::from("hunter2"))));
+        assert_eq!(lookup("cmd:true"), Ok(Some(String::new())));
+
+        let e = lookup("cmd:echo hunter2; exit 3").unwrap_err();
+        assert_eq!(e, "`echo hunter2; exit 3` failed with exit status: 3");
+    }
+
+    #[test]
+    fn nested_tables_and_arrays() {
+        std::env::set_var("RUSTYSTATUS_TEST_NESTED", "hunter2");
+        let mut t = table(
+            "[net]\nheaders = [\"env:RUSTYSTATUS_TEST_NESTED\", \"plain\"]\n[net.auth]\ntoken = \"env:RUSTYSTATUS_TEST_NESTED\"\n",
+        );
+        resolve(&mut t).unwrap();
+        let expected = table("[net]\nheaders = [\"hunter2\", \"plain\"]\n[net.auth]\ntoken = \"hunter2\"\n");
+        assert_eq!(t, expected);
+    }
+
+    #[test]
+    fn errors_name_the_key_but_not_the_value() {
+        std::env::set_var("RUSTYSTATUS_TEST_LEAK", "hunter2");
+        std::env::remove_var("RUSTYSTATUS_TEST_MISSING");
+        let mut t = table(
+            "[weather]\ncity = \"env:RUSTYSTATUS_TEST_LEAK\"\n[weather.auth]\napikey = \"env:RUSTYSTATUS_TEST_MISSING\"\n",
+        );
+        let (path, e) = resolve(&mut t).unwrap_err();
+        assert_eq!(path, ["weather", "auth", "apikey"]);
+        assert!(!e.contains("hunter2"), "{}", e);
+
+        // through the config, with the line
+        let text = "[weather]\napikey = \"cmd:printf $RUSTYSTATUS_TEST_LEAK; false\"\n";
+        let e = crate::Config::parse(text).err().unwrap().to_string();
+        assert_eq!(
+            e,
+            "`config.toml` line 2: `weather.apikey`: `printf $RUSTYSTATUS_TEST_LEAK; false` failed with exit status: 1"
+        );
+    }
+}
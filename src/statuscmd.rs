@@ -0,0 +1,144 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::thread;
+
+use crate::click::{Actions, Click, SharedActions};
+use crate::worker::lock;
+use crate::Result;
+
+// dwm with the statuscmd patch reads the byte below ' ' in front of each block
+// in the status. With the statuscmd-signal variant (as used with dwmblocks) a
+// click sends SIGRTMIN+byte to rustystatus, carrying the button as its value.
+// With the command variant dwm runs a command with $BUTTON set instead, which
+// can write `BLOCK BUTTON` to the fifo, BLOCK being the byte or block name.
+
+/// Highest marker byte, so that SIGRTMIN+byte stays below SIGRTMAX.
+pub(crate) const MAX_SIGNAL: u8 = 30;
+
+/// Runs the `on_click` action of the block behind the marker byte of every
+/// realtime signal dwm sends.
+pub(crate) fn listen_signals(actions: SharedActions) -> Result<()> {
+    // the handler may only do async-signal-safe work, so it writes the
+    // marker and button to a socket that a thread reads from
+    let (mut reader, writer) = UnixStream::pair()?;
+    let fd = writer.as_raw_fd();
+
+    // every marker, as a reloaded config might hand them out differently
+    for n in 1..=MAX_SIGNAL {
+        let handler = move |info: &libc::siginfo_t| {
+            let button = unsafe { info.si_value().sival_ptr } as usize as u8;
+            let buf = [n, button];
+            unsafe {
+                libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len());
+            }
+        };
+        unsafe {
+            signal_hook_registry::register_sigaction(libc::SIGRTMIN() + i32::from(n), handler)?;
+        }
+    }
+
+    thread::spawn(move || {
+        // the handlers write to it for as long as rustystatus runs
+        let _writer = writer;
+        let mut buf = [0; 2];
+        while reader.read_exact(&mut buf).is_ok() {
+            let actions = lock(&actions);
+            if let Some(name) = actions.signal_block(buf[0]) {
+                actions.handle(&Click {
+                    name: Some(name.to_string()),
+                    instance: None,
+                    button: buf[1],
+                });
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Runs the `on_click` action for every `BLOCK BUTTON` line written to the
+/// fifo at `path`, creating it if needed.
+pub(crate) fn listen_fifo(path: &Path, actions: SharedActions) -> Result<()> {
+    if !path.exists() {
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+            let e = std::io::Error::last_os_error();
+            return Err(format!("could not create fifo `{}`: {}", path.display(), e).into());
+        }
+    }
+
+    let path = path.to_path_buf();
+    thread::spawn(move || loop {
+        // opening blocks until a writer shows up, and reading ends with the
+        // last writer, so open it again for the next click
+        let fifo = match File::open(&path) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("Error: could not open fifo `{}`: {}", path.display(), e);
+                return;
+            }
+        };
+
+        for line in BufReader::new(fifo).lines().map_while(|l| l.ok()) {
+            let actions = lock(&actions);
+            match parse_line(&line, &actions) {
+                Some(click) => actions.handle(&click),
+                None => eprintln!("Error: invalid statuscmd line `{}`, expected `BLOCK BUTTON`", line),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn parse_line(line: &str, actions: &Actions) -> Option<Click> {
+    let mut words = line.split_whitespace();
+    let block = words.next()?;
+    let button = words.next()?.parse().ok()?;
+
+    let name = match block.parse::<u8>() {
+        Ok(n) => actions.signal_block(n)?.to_string(),
+        Err(_) => block.to_string(),
+    };
+
+    Some(Click {
+        name: Some(name),
+        instance: None,
+        button,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use std::collections::HashMap;
+
+    fn click(name: &str, button: u8) -> Option<Click> {
+        Some(Click {
+            name: Some(name.to_string()),
+            instance: None,
+            button,
+        })
+    }
+
+    #[test]
+    fn parses_marker_or_name_and_button() {
+        let signals = vec![(1, String::from("cpu")), (2, String::from("bat"))];
+        let signals = signals.into_iter().collect();
+        let actions = Actions::new(&Config::default(), HashMap::new(), signals);
+
+        assert_eq!(parse_line("2 1", &actions), click("bat", 1));
+        assert_eq!(parse_line("  cpu\t3 ", &actions), click("cpu", 3));
+        // no block has marker 7
+        assert_eq!(parse_line("7 1", &actions), None);
+        assert_eq!(parse_line("cpu", &actions), None);
+        assert_eq!(parse_line("cpu left", &actions), None);
+        assert_eq!(parse_line("", &actions), None);
+    }
+}
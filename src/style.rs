@@ -0,0 +1,269 @@
+use crate::format::leading_number;
+use crate::schema::suggest;
+use crate::{Config, Result};
+
+// [cpu]
+// icon = " "
+// color = "#bbbbbb"
+// thresholds = [
+//     { above = 50, color = "#ffff00" },
+//     { above = 80, color = "#ff0000" },
+// ]
+
+const THRESHOLD_KEYS: &[&str] = &["above", "below", "color"];
+
+#[derive(Debug, PartialEq, Clone)]
+struct Threshold {
+    above: Option<f64>,
+    below: Option<f64>,
+    color: String,
+}
+
+/// The icon and colors of a block, from its config table.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub(crate) struct Style {
+    icon: Option<String>,
+    color: Option<String>,
+    thresholds: Vec<Threshold>,
+}
+
+impl Style {
+    pub(crate) fn new(config: &Config, name: &str) -> Result<Style> {
+        let color = config.block_str(name, "color")?.map(|c| c.to_string());
+        if let Some(c) = &color {
+            check_color(c).map_err(|e| config.error_at(&[name, "color"], format!("`{}.color`: {}", name, e)))?;
+        }
+
+        let mut thresholds = Vec::new();
+        let list = match config.block(name).and_then(|t| t.get("thresholds")) {
+            Some(toml::Value::Array(a)) => a.as_slice(),
+            Some(_) => {
+                let msg = format!("`{}.thresholds` must be an array of tables", name);
+                return Err(config.error_at(&[name, "thresholds"], msg));
+            }
+            None => &[],
+        };
+
+        for (i, t) in list.iter().enumerate() {
+            let key = format!("{}.thresholds[{}]", name, i);
+            let error = |msg: String| config.error_at(&[name, "thresholds"], msg);
+
+            let table = t.as_table().ok_or_else(|| error(format!("`{}` must be a table", key)))?;
+            let mut keys: Vec<_> = table.keys().collect();
+            keys.sort();
+            if let Some(k) = keys.into_iter().find(|k| !THRESHOLD_KEYS.contains(&k.as_str())) {
+                let hint = suggest(k, THRESHOLD_KEYS.iter());
+                return Err(error(format!("unknown key `{}.{}`{}", key, k, hint)));
+            }
+            let number = |k| match t.get(k) {
+                Some(toml::Value::Integer(n)) => Ok(Some(*n as f64)),
+                Some(toml::Value::Float(n)) => Ok(Some(*n)),
+                Some(_) => Err(error(format!("`{}.{}` must be a number", key, k))),
+                None => Ok(None),
+            };
+
+            let above = number("above")?;
+            let below = number("below")?;
+            if above.is_none() && below.is_none() {
+                return Err(error(format!("`{}` needs `above` or `below`", key)));
+            }
+
+            let color = t
+                .get("color")
+                .and_then(|c| c.as_str())
+                .ok_or_else(|| error(format!("`{}.color` must be a string", key)))?;
+            check_color(color).map_err(|e| error(format!("`{}.color`: {}", key, e)))?;
+
+            thresholds.push(Threshold {
+                above,
+                below,
+                color: color.to_string(),
+            });
+        }
+
+        Ok(Style {
+            icon: config.block_str(name, "icon")?.map(|i| i.to_string()),
+            color,
+            thresholds,
+        })
+    }
+
+    /// Puts the icon in front of `out` and colors both with status2d markup.
+    ///
+    /// `value` is the block's raw output, compared against the thresholds,
+    /// of which the last one that matches picks the color.
+    /// Placeholders with an instance, like `{bat.watts}`, only get the
+    /// default color, as the icon and thresholds are meant for the main value.
+    pub(crate) fn apply(&self, placeholder: &str, value: &str, out: String) -> String {
+        let main = !placeholder.contains('.');
+        let out = match &self.icon {
+            Some(icon) if main => format!("{}{}", icon, out),
+            _ => out,
+        };
+
+        let n = leading_number(value).filter(|_| main);
+        let color = self
+            .thresholds
+            .iter()
+            .rev()
+            .find(|t| match n {
+                Some(n) => t.above.is_none_or(|a| n > a) && t.below.is_none_or(|b| n < b),
+                None => false,
+            })
+            .map(|t| &t.color)
+            .or(self.color.as_ref());
+
+        match color {
+            Some(c) => format!("^c{}^{}^d^", c, out),
+            None => out,
+        }
+    }
+}
+
+fn check_color(c: &str) -> std::result::Result<(), String> {
+    let hex = c.strip_prefix('#').unwrap_or("");
+    if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(format!("`{}` is not a color like `#rrggbb`", c))
+    }
+}
+
+/// A run of text in a single color.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Span<'a> {
+    pub text: &'a str,
+    pub color: Option<&'a str>,
+}
+
+/// Splits `s` at its status2d color markup, `^c#rrggbb^` and `^d^`.
+///
+/// `color` is the color `s` starts in and is left at the one it ends in, so
+/// markup can carry over from one segment to the next like it does in dwm.
+/// Other status2d commands are dropped, as no backend but dwm could show them.
+pub(crate) fn spans<'a>(s: &'a str, color: &mut Option<&'a str>) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    let mut rest = s;
+
+    while let Some(start) = rest.find('^') {
+        let end = match rest[start + 1..].find('^') {
+            Some(e) => start + 1 + e,
+            None => break,
+        };
+
+        if start > 0 {
+            spans.push(Span {
+                text: &rest[..start],
+                color: *color,
+            });
+        }
+
+        let cmd = &rest[start + 1..end];
+        if let Some(c) = cmd.strip_prefix('c') {
+            *color = Some(c);
+        } else if cmd == "d" {
+            *color = None;
+        }
+        rest = &rest[end + 1..];
+    }
+
+    if !rest.is_empty() {
+        spans.push(Span {
+            text: rest,
+            color: *color,
+        });
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(text: &str) -> Result<Style> {
+        Style::new(&Config::parse(text)?, "cpu")
+    }
+
+    #[test]
+    fn unknown_threshold_keys() {
+        let e = style("[cpu]\nthresholds = [\n    { above = 50, belw = 90, color = \"#ff0000\" },\n]\n").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "`config.toml` line 2: unknown key `cpu.thresholds[0].belw`, did you mean `below`?"
+        );
+        let e = style("[cpu]\nthresholds = [50]\n").unwrap_err();
+        assert_eq!(e.to_string(), "`config.toml` line 2: `cpu.thresholds[0]` must be a table");
+    }
+
+    const CONFIG: &str = "[cpu]
+icon = \"C \"
+color = \"#bbbbbb\"
+thresholds = [
+    { above = 50, color = \"#ffff00\" },
+    { above = 80, color = \"#ff0000\" },
+    { below = 5, color = \"#00ff00\" },
+]
+";
+
+    #[test]
+    fn last_matching_threshold_wins() {
+        let style = style(CONFIG).unwrap();
+        let apply = |value: &str| style.apply("cpu", value, format!("{}%", value));
+        assert_eq!(apply("90"), "^c#ff0000^C 90%^d^");
+        assert_eq!(apply("60"), "^c#ffff00^C 60%^d^");
+        assert_eq!(apply("50"), "^c#bbbbbb^C 50%^d^");
+        assert_eq!(apply("3"), "^c#00ff00^C 3%^d^");
+        // not a number, like `N/A`
+        assert_eq!(apply("off"), "^c#bbbbbb^C off%^d^");
+    }
+
+    #[test]
+    fn sub_placeholders_get_the_default_color_only() {
+        let style = style(CONFIG).unwrap();
+        assert_eq!(style.apply("cpu.max", "95", String::from("95")), "^c#bbbbbb^95^d^");
+        assert_eq!(Style::default().apply("cpu", "95", String::from("95")), "95");
+    }
+
+    #[test]
+    fn checks_colors() {
+        let e = style("[cpu]\ncolor = \"red\"\n").unwrap_err();
+        assert_eq!(e.to_string(), "`config.toml` line 2: `cpu.color`: `red` is not a color like `#rrggbb`");
+        let e = style("[cpu]\nthresholds = [{ above = 1, color = \"#12345\" }]\n").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "`config.toml` line 2: `cpu.thresholds[0].color`: `#12345` is not a color like `#rrggbb`"
+        );
+        let e = style("[cpu]\nthresholds = [{ color = \"#123456\" }]\n").unwrap_err();
+        assert_eq!(e.to_string(), "`config.toml` line 2: `cpu.thresholds[0]` needs `above` or `below`");
+    }
+
+    fn span<'a>(text: &'a str, color: Option<&'a str>) -> Span<'a> {
+        Span { text, color }
+    }
+
+    #[test]
+    fn spans_carry_the_color_over() {
+        let mut color = None;
+        assert_eq!(
+            spans("a ^c#ff0000^b", &mut color),
+            [span("a ", None), span("b", Some("#ff0000"))]
+        );
+        // the next segment starts where the last one left off
+        assert_eq!(
+            spans("c^d^d", &mut color),
+            [span("c", Some("#ff0000")), span("d", None)]
+        );
+        assert_eq!(color, None);
+    }
+
+    #[test]
+    fn spans_drop_other_commands_and_keep_unterminated_ones() {
+        let mut color = Some("#bbbbbb");
+        assert_eq!(
+            spans("^r0,0,5,5^x^c#00ff00", &mut color),
+            [span("x^c#00ff00", Some("#bbbbbb"))]
+        );
+        assert!(spans("^d^", &mut color).is_empty());
+        assert_eq!(color, None);
+    }
+}
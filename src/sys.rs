@@ -0,0 +1,128 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the blocks read `/proc`, `/sys` and `/run` from.
+///
+/// Paths are always absolute, like `/proc/stat`, and are up to the
+/// implementation to map somewhere else.
+pub trait Fs: Send + Sync + fmt::Debug {
+    /// Reads the file at `path`.
+    fn read_bytes(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Reads the text file at `path`.
+    fn read(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read_bytes(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Names of the entries in directory `path`, sorted.
+    fn list(&self, path: &Path) -> io::Result<Vec<String>>;
+}
+
+/// The real filesystem, below `root`, which is `/` unless `fs_root` is set
+/// in the config, e.g. to read the host's `/proc` mounted in a container.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Root {
+    root: PathBuf,
+}
+
+impl Root {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Root {
+        Root { root: root.into() }
+    }
+
+    fn path(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
+
+impl Fs for Root {
+    fn read_bytes(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(self.path(path))
+    }
+
+    fn list(&self, path: &Path) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(self.path(path))? {
+            names.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// The captured machine `name` in `tests/fixtures`, like `laptop`.
+#[cfg(test)]
+pub(crate) fn fixture(name: &str) -> std::sync::Arc<Root> {
+    std::sync::Arc::new(Root::new(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)))
+}
+
+/// Initializes a block from `config` reading from `fs`, and samples it once.
+#[cfg(test)]
+pub(crate) fn sampled<B: crate::block::Block>(config: &str, fs: std::sync::Arc<dyn Fs>) -> B {
+    let mut block = B::init(&crate::Config::parse(config).unwrap().with_fs(fs)).unwrap();
+    block.update();
+    block
+}
+
+/// Files kept in memory, to change between two updates of a block.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct MockFs {
+    files: std::sync::Mutex<std::collections::BTreeMap<PathBuf, Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl MockFs {
+    pub(crate) fn set(&self, path: &str, content: &str) {
+        self.files.lock().unwrap().insert(PathBuf::from(path), content.as_bytes().to_vec());
+    }
+}
+
+#[cfg(test)]
+impl Fs for MockFs {
+    fn read_bytes(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let files = self.files.lock().unwrap();
+        files.get(path).cloned().ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn list(&self, path: &Path) -> io::Result<Vec<String>> {
+        let files = self.files.lock().unwrap();
+        let mut names: Vec<_> = files
+            .keys()
+            .filter_map(|p| p.strip_prefix(path).ok()?.components().next())
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        names.dedup();
+        if names.is_empty() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_maps_absolute_paths() {
+        let root = fixture("laptop");
+        assert!(root.read(Path::new("/proc/stat")).unwrap().starts_with("cpu "));
+        assert_eq!(
+            root.list(Path::new("/sys/class/power_supply")).unwrap(),
+            ["AC", "BAT0", "BAT1", "hidpp_battery_0"]
+        );
+    }
+
+    #[test]
+    fn mock_lists_direct_children() {
+        let fs = MockFs::default();
+        fs.set("/sys/a/x", "1");
+        fs.set("/sys/a/y/z", "2");
+        fs.set("/sys/ab", "3");
+        assert_eq!(fs.list(Path::new("/sys/a")).unwrap(), ["x", "y"]);
+        assert_eq!(fs.read(Path::new("/sys/a/y/z")).unwrap(), "2");
+        assert!(fs.read(Path::new("/sys/a/nope")).is_err());
+    }
+}
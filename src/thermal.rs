@@ -0,0 +1,226 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::block::check_placeholder;
+use crate::sys::Fs;
+use crate::{Args, Block, Config};
+
+// {temp}  temperature of the CPU, or of `sensor`, e.g. `67°C`
+//
+// [thermal]
+// sensor = "Tctl"    # a hwmon label, hwmon chip or thermal zone type
+// unit = "F"         # C unless set
+
+const HWMON: &str = "/sys/class/hwmon";
+const THERMAL: &str = "/sys/class/thermal";
+
+/// Labels of the CPU package temperature, of Intel's coretemp and AMD's
+/// k10temp, looked for without `sensor`.
+const CPU_LABELS: &[&str] = &["Package id 0", "Tctl", "Tdie"];
+
+/// The thermal zone of Intel CPUs, for when no hwmon chip has a label.
+const CPU_ZONE: &str = "x86_pkg_temp";
+
+#[derive(Debug, PartialEq, Clone)]
+struct Sensor {
+    /// The hwmon chip, like `coretemp`, or the type of a thermal zone.
+    chip: String,
+    label: Option<String>,
+    celsius: f64,
+}
+
+impl Sensor {
+    fn is(&self, name: &str) -> bool {
+        self.label.as_deref() == Some(name) || self.chip == name
+    }
+}
+
+#[derive(Debug)]
+pub struct Thermal {
+    celsius: Option<f64>,
+    sensor: Option<String>,
+    fahrenheit: bool,
+    fs: Arc<dyn Fs>,
+}
+
+impl Block for Thermal {
+    fn init(config: &Config) -> crate::Result<Thermal> {
+        let fahrenheit = match config.block_str("thermal", "unit")? {
+            None | Some("C") => false,
+            Some("F") => true,
+            Some(u) => {
+                let msg = format!("`thermal.unit`: unknown unit `{}`, expected `C` or `F`", u);
+                return Err(config.error_at(&["thermal", "unit"], msg));
+            }
+        };
+
+        Ok(Thermal {
+            celsius: None,
+            sensor: config.block_str("thermal", "sensor")?.map(|s| s.to_string()),
+            fahrenheit,
+            fs: config.fs(),
+        })
+    }
+
+    fn update(&mut self) {
+        let sensors = read_sensors(&*self.fs);
+        let sensor = match &self.sensor {
+            Some(name) => sensors.iter().find(|s| s.is(name)),
+            None => pick_cpu(&sensors),
+        };
+
+        self.celsius = sensor.map(|s| s.celsius);
+        if self.celsius.is_none() {
+            match &self.sensor {
+                Some(name) => eprintln!("Error: no temperature sensor `{}` in `{}` or `{}`", name, HWMON, THERMAL),
+                None => eprintln!("Error: no temperature sensor in `{}` or `{}`", HWMON, THERMAL),
+            }
+        }
+    }
+
+    fn output(&self, _placeholder: &str, _args: &Args) -> Option<String> {
+        let celsius = self.celsius?;
+        if self.fahrenheit {
+            Some(format!("{:.0}°F", celsius * 9.0 / 5.0 + 32.0))
+        } else {
+            Some(format!("{:.0}°C", celsius))
+        }
+    }
+
+    fn check_args(&self, placeholder: &str, _args: &Args) -> crate::Result<()> {
+        check_placeholder(placeholder, &["temp"])
+    }
+
+    fn config_keys() -> &'static [&'static str] {
+        &["sensor", "unit"]
+    }
+}
+
+/// The CPU package sensor, or the first one when none is known.
+fn pick_cpu(sensors: &[Sensor]) -> Option<&Sensor> {
+    CPU_LABELS
+        .iter()
+        .find_map(|l| sensors.iter().find(|s| s.label.as_deref() == Some(*l)))
+        .or_else(|| sensors.iter().find(|s| s.chip == CPU_ZONE))
+        .or_else(|| sensors.first())
+}
+
+/// Every `temp*_input` of the hwmon chips, then every thermal zone.
+fn read_sensors(fs: &dyn Fs) -> Vec<Sensor> {
+    let mut sensors = Vec::new();
+    let millis = |path: &Path| fs.read(path).ok()?.trim().parse::<f64>().ok().map(|m| m / 1000.0);
+
+    for hwmon in fs.list(Path::new(HWMON)).unwrap_or_default() {
+        let dir = Path::new(HWMON).join(hwmon);
+        let chip = match fs.read(&dir.join("name")) {
+            Ok(name) => name.trim().to_string(),
+            Err(_) => continue,
+        };
+
+        let mut inputs: Vec<_> = fs
+            .list(&dir)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|f| {
+                let n: u32 = f.strip_prefix("temp")?.strip_suffix("_input")?.parse().ok()?;
+                Some(n)
+            })
+            .collect();
+        // temp10 after temp9
+        inputs.sort_unstable();
+
+        for n in inputs {
+            let celsius = match millis(&dir.join(format!("temp{}_input", n))) {
+                Some(c) => c,
+                None => continue,
+            };
+            let label = fs.read(&dir.join(format!("temp{}_label", n))).ok().map(|l| l.trim().to_string());
+            sensors.push(Sensor {
+                chip: chip.clone(),
+                label,
+                celsius,
+            });
+        }
+    }
+
+    for zone in fs.list(Path::new(THERMAL)).unwrap_or_default() {
+        if !zone.starts_with("thermal_zone") {
+            continue;
+        }
+        let dir = Path::new(THERMAL).join(zone);
+        if let (Ok(chip), Some(celsius)) = (fs.read(&dir.join("type")), millis(&dir.join("temp"))) {
+            sensors.push(Sensor {
+                chip: chip.trim().to_string(),
+                label: None,
+                celsius,
+            });
+        }
+    }
+
+    sensors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::{fixture, sampled, MockFs};
+
+    fn temp(config: &str, fs: Arc<dyn Fs>) -> Option<String> {
+        sampled::<Thermal>(config, fs).output("temp", &Args::default())
+    }
+
+    #[test]
+    fn reads_hwmon_and_thermal_zones() {
+        let sensors = read_sensors(&*fixture("laptop"));
+        let names: Vec<_> = sensors.iter().map(|s| (s.chip.as_str(), s.label.as_deref(), s.celsius)).collect();
+        assert_eq!(
+            names,
+            [
+                ("acpitz", None, 54.0),
+                ("nvme", Some("Composite"), 38.85),
+                ("coretemp", Some("Package id 0"), 67.0),
+                ("coretemp", Some("Core 0"), 66.0),
+                ("coretemp", Some("Core 1"), 71.0),
+                ("acpitz", None, 54.0),
+                ("x86_pkg_temp", None, 67.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_the_cpu_package() {
+        assert_eq!(temp("", fixture("laptop")).as_deref(), Some("67°C"));
+        assert_eq!(temp("", fixture("server")).as_deref(), Some("81°C"));
+        assert_eq!(temp("", fixture("desktop")), None);
+
+        let fs = MockFs::default();
+        fs.set("/sys/class/thermal/thermal_zone0/type", "acpitz\n");
+        fs.set("/sys/class/thermal/thermal_zone0/temp", "48000\n");
+        fs.set("/sys/class/thermal/thermal_zone1/type", "x86_pkg_temp\n");
+        fs.set("/sys/class/thermal/thermal_zone1/temp", "52500\n");
+        assert_eq!(temp("", Arc::new(fs)).as_deref(), Some("52°C"));
+    }
+
+    #[test]
+    fn sensor_by_label_or_chip() {
+        assert_eq!(temp("[thermal]\nsensor = \"Core 1\"\n", fixture("laptop")).as_deref(), Some("71°C"));
+        assert_eq!(temp("[thermal]\nsensor = \"nvme\"\n", fixture("laptop")).as_deref(), Some("39°C"));
+        assert_eq!(temp("[thermal]\nsensor = \"Tccd1\"\n", fixture("server")).as_deref(), Some("74°C"));
+        assert_eq!(temp("[thermal]\nsensor = \"gpu\"\n", fixture("laptop")), None);
+    }
+
+    #[test]
+    fn fahrenheit() {
+        assert_eq!(temp("[thermal]\nunit = \"F\"\n", fixture("laptop")).as_deref(), Some("153°F"));
+        let e = Thermal::init(&Config::parse("[thermal]\nunit = \"K\"\n").unwrap()).unwrap_err();
+        assert_eq!(e.to_string(), "`config.toml` line 2: `thermal.unit`: unknown unit `K`, expected `C` or `F`");
+    }
+
+    #[test]
+    fn only_temp() {
+        let thermal = Thermal::init(&Config::default()).unwrap();
+        assert!(thermal.check_args("temp", &Args::default()).is_ok());
+        let e = thermal.check_args("temp.foo", &Args::default()).unwrap_err();
+        assert_eq!(e.to_string(), "expected `temp`");
+    }
+}
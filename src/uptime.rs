@@ -0,0 +1,98 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::block::check_placeholder;
+use crate::sys::Fs;
+use crate::{Args, Block, Config};
+
+// {uptime}  time since boot, like `3d 4h`, `4h 12m` or `12m`
+
+#[derive(Debug)]
+pub struct Uptime {
+    /// Seconds since boot.
+    val: Option<f64>,
+    fs: Arc<dyn Fs>,
+}
+
+impl Block for Uptime {
+    fn init(config: &Config) -> crate::Result<Uptime> {
+        Ok(Uptime {
+            val: None,
+            fs: config.fs(),
+        })
+    }
+
+    fn update(&mut self) {
+        // seconds since boot and seconds the cores spent idle
+        // 277384.52 1043233.61
+        match self.fs.read(Path::new("/proc/uptime")) {
+            Ok(s) => {
+                self.val = s.split_whitespace().next().and_then(|u| u.parse().ok());
+                if self.val.is_none() {
+                    eprintln!("Error: failed parsing `/proc/uptime`");
+                }
+            }
+            Err(e) => {
+                self.val = None;
+                eprintln!("Error: `/proc/uptime` {}", e);
+            }
+        }
+    }
+
+    fn output(&self, _placeholder: &str, _args: &Args) -> Option<String> {
+        self.val.map(|secs| human(secs as u64))
+    }
+
+    fn check_args(&self, placeholder: &str, _args: &Args) -> crate::Result<()> {
+        check_placeholder(placeholder, &["uptime"])
+    }
+
+    // the minutes flip with the uptime, not with the wall clock
+    fn until_change(&self, _placeholders: &[(&str, &Args)]) -> Option<Duration> {
+        let millis = (self.val? * 1000.0).round() as u64;
+        Some(Duration::from_millis(60_000 - millis % 60_000))
+    }
+}
+
+/// The two largest units of `secs`, leaving out seconds.
+fn human(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::{fixture, sampled};
+
+    #[test]
+    fn reads_proc_uptime() {
+        let out = |name| sampled::<Uptime>("", fixture(name)).output("uptime", &Args::default());
+        assert_eq!(out("laptop").as_deref(), Some("3d 5h"));
+        assert_eq!(out("server").as_deref(), Some("73d 6h"));
+        assert_eq!(out("desktop").as_deref(), Some("48m"));
+    }
+
+    #[test]
+    fn two_largest_units() {
+        assert_eq!(human(59), "0m");
+        assert_eq!(human(3599), "59m");
+        assert_eq!(human(3600), "1h 0m");
+        assert_eq!(human(86399), "23h 59m");
+        assert_eq!(human(86400 + 3 * 3600 + 59), "1d 3h");
+    }
+
+    #[test]
+    fn changes_on_the_next_minute_of_uptime() {
+        // 2923.40 seconds are 48 minutes and 43.4 seconds
+        let until = sampled::<Uptime>("", fixture("desktop")).until_change(&[]).unwrap();
+        assert_eq!(until.as_millis(), 16600);
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::block::check_placeholder;
+use crate::sys::Fs;
+use crate::{Args, Block, Config};
+
+// {users}           number of users logged in, each counted once
+// {users.sessions}  number of logins, like `who | wc -l`
+// {users.names}     who is logged in, comma separated
+
+const PLACEHOLDERS: &[&str] = &["users", "users.sessions", "users.names"];
+
+/// Where glibc keeps the logins, `/var/run` links to `/run` nowadays.
+const UTMP: &[&str] = &["/run/utmp", "/var/run/utmp"];
+
+/// Size of a `struct utmp` of glibc, the same on 32 and 64 bit.
+const RECORD: usize = 384;
+/// Offset and length of `ut_user`.
+const USER: (usize, usize) = (44, 32);
+/// `ut_type` of a logged in user.
+const USER_PROCESS: i16 = 7;
+
+#[derive(Debug)]
+pub struct Users {
+    /// The user of every login, sorted.
+    val: Option<Vec<String>>,
+    fs: Arc<dyn Fs>,
+}
+
+impl Block for Users {
+    fn init(config: &Config) -> crate::Result<Users> {
+        Ok(Users {
+            val: None,
+            fs: config.fs(),
+        })
+    }
+
+    fn update(&mut self) {
+        let utmp = UTMP.iter().find_map(|p| self.fs.read_bytes(Path::new(p)).ok());
+        self.val = utmp.map(|u| logins(&u));
+        if self.val.is_none() {
+            eprintln!("Error: could not read `{}`", UTMP.join("` or `"));
+        }
+    }
+
+    fn output(&self, placeholder: &str, _args: &Args) -> Option<String> {
+        let logins = self.val.as_ref()?;
+        let mut names = logins.clone();
+        names.dedup();
+        match placeholder {
+            "users" => Some(names.len().to_string()),
+            "users.sessions" => Some(logins.len().to_string()),
+            "users.names" => Some(names.join(",")),
+            _ => None,
+        }
+    }
+
+    fn interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(10))
+    }
+
+    fn check_args(&self, placeholder: &str, _args: &Args) -> crate::Result<()> {
+        check_placeholder(placeholder, PLACEHOLDERS)
+    }
+
+    fn placeholders() -> &'static [&'static str] {
+        PLACEHOLDERS
+    }
+}
+
+/// The user names of the `USER_PROCESS` records of `utmp`, sorted.
+fn logins(utmp: &[u8]) -> Vec<String> {
+    let mut names: Vec<_> = utmp
+        .chunks_exact(RECORD)
+        .filter(|r| i16::from_ne_bytes([r[0], r[1]]) == USER_PROCESS)
+        .map(|r| {
+            let user = &r[USER.0..USER.0 + USER.1];
+            // NUL padded, but not terminated when it is 32 long
+            let len = user.iter().position(|&b| b == 0).unwrap_or(USER.1);
+            String::from_utf8_lossy(&user[..len]).into_owned()
+        })
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::{fixture, sampled};
+
+    #[test]
+    fn counts_user_processes() {
+        // a boot, run level, getty and dead record besides the three logins
+        let users: Users = sampled("", fixture("laptop"));
+        let out: Vec<_> = PLACEHOLDERS.iter().map(|p| users.output(p, &Args::default()).unwrap()).collect();
+        assert_eq!(out, ["2", "3", "alice,bob"]);
+    }
+
+    #[test]
+    fn without_utmp() {
+        assert_eq!(sampled::<Users>("", fixture("desktop")).output("users", &Args::default()), None);
+    }
+
+    #[test]
+    fn long_names_and_partial_records() {
+        let mut record = vec![0; RECORD];
+        record[..2].copy_from_slice(&USER_PROCESS.to_ne_bytes());
+        record[USER.0..USER.0 + USER.1].copy_from_slice(&[b'x'; 32]);
+        // a record being written while read
+        let mut utmp = record.clone();
+        utmp.extend_from_slice(&record[..100]);
+        assert_eq!(logins(&utmp), ["x".repeat(32)]);
+    }
+}
@@ -1,43 +1,37 @@
 use std::string::String;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use std::error::Error;
 use weathererror::*;
 
+use crate::{Args, Block, Config};
+
 // https://home.openweathermap.org
 // https://api.openweathermap.org/data/2.5/weather?q={CITY_ID}&appid={API_KEY}
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug)]
 pub struct Weather {
     description: Option<String>,
     degrees: Option<i8>,
     url: String,
-    five_min: Duration,
-    last_update: Option<Instant>,
+    client: reqwest::Client,
 }
 
-impl Weather {
-    pub fn init(url: String) -> Weather {
-        Weather {
+impl Block for Weather {
+    fn init(config: &Config) -> crate::Result<Weather> {
+        let timeout = config
+            .block_secs("weather", "timeout")?
+            .unwrap_or(Duration::from_secs(10));
+
+        Ok(Weather {
             description: None,
             degrees: None,
-            url,
-            five_min: Duration::from_secs(300),
-            last_update: None,
-        }
-    }
-
-    pub fn update(&mut self) {
-        if let Some(t) = self.last_update {
-            if t.elapsed() >= self.five_min {
-                Weather::update_vals(self);
-            }
-        } else {
-            Weather::update_vals(self);
-        }
+            url: config.format_url()?,
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+        })
     }
 
-    fn update_vals(&mut self) {
-        match get_weather(&self.url) {
+    fn update(&mut self) {
+        match get_weather(&self.client, &self.url) {
             Ok(t) => {
                 self.description = Some(t.0);
                 self.degrees = Some(t.1);
@@ -45,21 +39,34 @@ impl Weather {
             Err(e) => {
                 self.description = None;
                 self.degrees = None;
-                eprintln!("Error: {}", e)
+                eprintln!("Error: {}", redact(&e.to_string()))
             },
         }
-        self.last_update = Some(Instant::now());
     }
 
-    pub fn output(&self) -> Option<String> {
+    fn output(&self, _placeholder: &str, _args: &Args) -> Option<String> {
         match (&self.description, &self.degrees) {
             (Some(descript), Some(degree)) => Some(format!("{} {}°C", descript, degree)),
             _ => None,
         }
     }
+
+    fn interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(300))
+    }
+
+    fn config_keys() -> &'static [&'static str] {
+        &["apikey", "city", "timeout"]
+    }
 }
 
-fn get_weather(url: &str) -> Result<(String, i8), Box<dyn Error>> {
+fn get_weather(client: &reqwest::Client, url: &str) -> Result<(String, i8), Box<dyn Error>> {
+    let json: serde_json::Value = client.get(url).send()?.json()?;
+    parse_weather(&json)
+}
+
+/// The description and temperature of an OpenWeatherMap response.
+fn parse_weather(json: &serde_json::Value) -> Result<(String, i8), Box<dyn Error>> {
     /* JSON FORMAT
     {
         "base":"stations",
@@ -77,45 +84,54 @@ fn get_weather(url: &str) -> Result<(String, i8), Box<dyn Error>> {
     }
     */
 
-    let json: serde_json::Value = reqwest::get(url)?.json()?;
-
     let degrees_cel = json
         .pointer("/main/temp")
-        .ok_or_else(|| WeatherError::NoTempVal)?
+        .ok_or(WeatherError::NoTempVal)?
         .as_f64()
-        .ok_or_else(|| WeatherError::F64Error)?
+        .ok_or(WeatherError::F64Error)?
         .round() as i8;
 
     let description = json.pointer("/weather/0/description")
-        .ok_or_else(|| WeatherError::NoDescriptionVal)?
+        .ok_or(WeatherError::NoDescriptionVal)?
         .as_str()
-        .ok_or_else(|| WeatherError::StrError)?
+        .ok_or(WeatherError::StrError)?
         .trim_matches('"')
         .capitalize_words();
 
     Ok((description, degrees_cel))
 }
 
+/// Hides the api key in `msg`, as http errors show the whole url.
+fn redact(msg: &str) -> String {
+    match msg.find("appid=") {
+        Some(i) => {
+            let start = i + "appid=".len();
+            let end = msg[start..]
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .map_or(msg.len(), |e| start + e);
+            format!("{}***{}", &msg[..start], &msg[end..])
+        }
+        None => msg.to_string(),
+    }
+}
+
 trait Capitalize {
     fn capitalize_words(&self) -> String;
 }
 
 impl Capitalize for str {
     fn capitalize_words(&self) -> String {
-        let mut new = String::new();
-        let words: Vec<_> = self.split_whitespace().collect();
-        let len = words.len() - 1;
-
-        for (num, word) in words.iter().enumerate() {
-            let mut chars = word.chars();
-            if let Some(c) = chars.next() {
-                new.push_str(&(c.to_uppercase().collect::<String>() + chars.as_str()));
-                if num != len {
-                    new.push_str(" ");
+        let words: Vec<_> = self
+            .split_whitespace()
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(c) => c.to_uppercase().collect::<String>() + chars.as_str(),
+                    None => String::new(),
                 }
-            }
-        }
-        new
+            })
+            .collect();
+        words.join(" ")
     }
 }
 
@@ -151,6 +167,39 @@ mod weathererror {
             }
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_response() {
+        let text = include_str!("../tests/fixtures/openweathermap.json");
+        let json: serde_json::Value = serde_json::from_str(text).unwrap();
+        assert_eq!(parse_weather(&json).unwrap(), ("Light Intensity Drizzle".to_string(), 8));
+    }
 
+    #[test]
+    fn missing_values_are_errors() {
+        let json = serde_json::json!({"main": {"temp": 1.2}, "weather": []});
+        assert_eq!(parse_weather(&json).unwrap_err().to_string(), WeatherError::NoDescriptionVal.to_string());
+        let json = serde_json::json!({"main": {"temp": "1"}});
+        assert_eq!(parse_weather(&json).unwrap_err().to_string(), WeatherError::F64Error.to_string());
+    }
 
+    #[test]
+    fn capitalizes_each_word() {
+        assert_eq!("overcast  clouds".capitalize_words(), "Overcast Clouds");
+        assert_eq!("".capitalize_words(), "");
+    }
+
+    #[test]
+    fn redacts_the_api_key() {
+        assert_eq!(
+            redact("error sending request for url (https://x/weather?id=1&appid=0123abc): timed out"),
+            "error sending request for url (https://x/weather?id=1&appid=***): timed out"
+        );
+        assert_eq!(redact("timed out"), "timed out");
+    }
 }
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::format::Placeholder;
+use crate::style::Style;
+use crate::{Block, Event};
+
+/// Longest a worker sleeps on `Block::until_change`, so a clock catches up
+/// soon after a suspend or a change of the system time.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Latest output of every placeholder, shared between the workers and the main loop.
+///
+/// `None` marks a placeholder hidden by its `min` or `max` option, a placeholder
+/// that never had a value is missing altogether.
+pub(crate) type Outputs = Arc<Mutex<HashMap<String, Option<String>>>>;
+
+/// Sent to a worker to act on its block outside of its interval.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Command {
+    /// Update the block now.
+    Refresh,
+    /// Pass `action` to `Block::action` and show the result.
+    Action { placeholder: String, action: String },
+}
+
+/// A block ready to be moved to its own thread.
+pub(crate) struct Worker {
+    pub name: String,
+    pub block: Box<dyn Block>,
+    pub interval: Duration,
+    /// Whether `interval` is set in the config, overriding `Block::until_change`.
+    pub fixed_interval: bool,
+    pub style: Style,
+    pub placeholders: Vec<Placeholder>,
+}
+
+impl Worker {
+    /// Updates the block and publishes its output, without a thread.
+    pub(crate) fn update_once(&mut self, outputs: &Outputs) {
+        self.block.update();
+        publish(self.block.as_ref(), &self.style, &self.placeholders, outputs);
+    }
+
+    /// Whether every placeholder of the block has had output.
+    pub(crate) fn has_output(&self, outputs: &Outputs) -> bool {
+        let outputs = lock(outputs);
+        self.placeholders.iter().all(|p| outputs.contains_key(p.key()))
+    }
+}
+
+/// Moves the block of `worker` to its own thread, updating it every interval
+/// and whenever a `Command::Refresh` arrives on `commands`.
+///
+/// After each update the outputs of its placeholders are published to
+/// `outputs`, keyed by `Placeholder::key` and dressed up by the block's style,
+/// and `Event::Changed` is sent to `events` if any of them differ from what
+/// was shown before.
+/// A placeholder without output keeps its last good value, so a failing or
+/// slow block never blanks or freezes the rest of the status.
+///
+/// The worker stops once the sender of `commands` is dropped.
+pub(crate) fn spawn(worker: Worker, outputs: Outputs, events: Sender<Event>, commands: Receiver<Command>) {
+    let Worker {
+        name,
+        mut block,
+        interval,
+        fixed_interval,
+        style,
+        placeholders,
+    } = worker;
+
+    let block_name = name.clone();
+    let spawned = thread::Builder::new()
+        .name(name.clone())
+        .spawn(move || {
+            let mut next_update = Instant::now();
+
+            loop {
+                block.update();
+
+                if publish(block.as_ref(), &style, &placeholders, &outputs) && events.send(Event::Changed).is_err() {
+                    // main loop is gone
+                    return;
+                }
+
+                let now = Instant::now();
+                match until_change(block.as_ref(), &placeholders, fixed_interval) {
+                    Some(d) => next_update = now + d,
+                    None => {
+                        // schedule from the due time so slow updates don't drift the interval
+                        next_update += interval;
+                        if next_update <= now {
+                            next_update = now + interval;
+                        }
+                    }
+                }
+
+                loop {
+                    let timeout = next_update.saturating_duration_since(Instant::now());
+                    match commands.recv_timeout(timeout) {
+                        Ok(Command::Refresh) => {
+                            next_update = Instant::now();
+                            break;
+                        }
+                        Ok(Command::Action { placeholder, action }) => {
+                            if let Err(e) = block.action(&placeholder, &action) {
+                                eprintln!("Error: `{}` action `{}`: {}", block_name, action, e);
+                            }
+                            if publish(block.as_ref(), &style, &placeholders, &outputs) {
+                                let _ = events.send(Event::Changed);
+                            }
+                            // the action may have switched to a pattern that changes sooner
+                            if let Some(d) = until_change(block.as_ref(), &placeholders, fixed_interval) {
+                                next_update = Instant::now() + d;
+                            }
+                        }
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+            }
+        });
+
+    if let Err(e) = spawned {
+        eprintln!("Error: could not start block `{}`: {}", name, e);
+    }
+}
+
+fn until_change(block: &dyn Block, placeholders: &[Placeholder], fixed_interval: bool) -> Option<Duration> {
+    if fixed_interval {
+        return None;
+    }
+    let args: Vec<_> = placeholders.iter().map(|p| (p.name(), p.args())).collect();
+    block.until_change(&args).map(|d| d.min(MAX_WAIT))
+}
+
+/// Locks `m`, even if a thread panicked while holding it.
+pub(crate) fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    match m.lock() {
+        Ok(g) => g,
+        Err(e) => e.into_inner(),
+    }
+}
+
+fn publish(block: &dyn Block, style: &Style, placeholders: &[Placeholder], outputs: &Outputs) -> bool {
+    let mut outputs = lock(outputs);
+
+    let mut changed = false;
+    for p in placeholders {
+        if let Some(out) = block.output(p.name(), p.args()) {
+            let out = if p.in_range(&out) {
+                Some(style.apply(p.name(), &out, p.pad(out.clone())))
+            } else {
+                None
+            };
+            if outputs.get(p.key()) != Some(&out) {
+                outputs.insert(p.key().to_string(), out);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Args, Config, Format};
+
+    struct Hourly;
+
+    impl Block for Hourly {
+        fn init(_config: &Config) -> crate::Result<Hourly> {
+            Ok(Hourly)
+        }
+
+        fn update(&mut self) {}
+
+        fn output(&self, _placeholder: &str, _args: &Args) -> Option<String> {
+            Some(String::from("12"))
+        }
+
+        fn until_change(&self, _placeholders: &[(&str, &Args)]) -> Option<Duration> {
+            Some(Duration::from_secs(3600))
+        }
+    }
+
+    #[test]
+    fn long_waits_are_capped() {
+        let format = Format::parse("{clock}").unwrap();
+        let placeholders: Vec<_> = format.placeholders().into_iter().cloned().collect();
+        assert_eq!(until_change(&Hourly, &placeholders, false), Some(MAX_WAIT));
+        // an `interval` in the config wins
+        assert_eq!(until_change(&Hourly, &placeholders, true), None);
+    }
+
+    #[test]
+    fn out_of_range_is_stored_as_hidden() {
+        let format = Format::parse("{clock:min=20} {clock:max=20} {clock}").unwrap();
+        let placeholders: Vec<_> = format.placeholders().into_iter().cloned().collect();
+        let outputs = Outputs::default();
+
+        assert!(publish(&Hourly, &Style::default(), &placeholders, &outputs));
+        let outputs = outputs.lock().unwrap();
+        assert_eq!(outputs.get("clock:min=20"), Some(&None));
+        assert_eq!(outputs.get("clock:max=20"), Some(&Some(String::from("12"))));
+        assert_eq!(outputs.get("clock"), Some(&Some(String::from("12"))));
+    }
+}
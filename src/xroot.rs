@@ -0,0 +1,53 @@
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, PropMode, Window};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt;
+
+use crate::Result;
+
+/// Sets `WM_NAME` of the X root window, which dwm shows as its status.
+///
+/// The connection is opened on first use and kept open. If the X server goes
+/// away the connection is dropped and opened again on the next call.
+#[derive(Default)]
+pub struct RootName {
+    conn: Option<(RustConnection, Window)>,
+}
+
+impl RootName {
+    pub fn new() -> RootName {
+        RootName { conn: None }
+    }
+
+    pub fn set(&mut self, name: &str) -> Result<()> {
+        if self.try_set(name).is_ok() {
+            return Ok(());
+        }
+
+        // the server might have restarted, retry once on a fresh connection
+        self.conn = None;
+        self.try_set(name)
+    }
+
+    fn try_set(&mut self, name: &str) -> Result<()> {
+        if self.conn.is_none() {
+            let (conn, screen) = x11rb::connect(None)?;
+            let root = conn.setup().roots[screen].root;
+            self.conn = Some((conn, root));
+        }
+
+        if let Some((conn, root)) = &self.conn {
+            // same as `xsetroot -name`, a STRING holding the raw bytes
+            conn.change_property8(
+                PropMode::REPLACE,
+                *root,
+                AtomEnum::WM_NAME,
+                AtomEnum::STRING,
+                name.as_bytes(),
+            )?
+            .check()?;
+        }
+
+        Ok(())
+    }
+}